@@ -0,0 +1,278 @@
+//! Which plans still need doing today, worked out from the local cache alone
+//! so reminders and the prompt don't wait on the network.
+
+use anyhow::Result;
+use chrono::{Datelike, Days, Local, NaiveDate, Weekday};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use uuid::Uuid;
+use crate::api::types::PlanStatus;
+use crate::cache::Cache;
+use crate::charts;
+use crate::parse::cadence::{Recurrence, plan_activity, plan_cadence};
+use crate::parse::log::{parse_log, singular};
+use crate::queue;
+
+#[derive(Serialize, Clone)]
+pub struct DuePlan {
+    pub id: Uuid,
+    pub goal: String,
+    pub schedule: String,
+}
+
+/// Whether a log counts towards a plan about `activity`, e.g. "pullup". The
+/// activity's words have to appear whole in the log, so "run" doesn't match "brunch".
+pub fn counts_towards(activity: &str, logged_activity: &str, text: &str) -> bool {
+    if logged_activity == activity {
+        return true;
+    }
+    let wanted = words(activity);
+    !wanted.is_empty() && words(text).windows(wanted.len()).any(|window| window == wanted)
+}
+
+/// Lowercase words in singular, ignoring numbers and punctuation.
+fn words(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| singular(word).to_string())
+        .collect()
+}
+
+/// Today's scheduled plans split by whether they've been logged, counting queued logs.
+pub struct Today {
+    pub done: Vec<DuePlan>,
+    pub due: Vec<DuePlan>,
+    /// Consecutive days with anything logged, see `charts::streaks`.
+    pub streak: u32,
+}
+
+pub fn plans_today(cache: &Cache, today: NaiveDate) -> Result<Today> {
+    let logs = logged(cache)?;
+    let mut agenda = Today { done: Vec::new(), due: Vec::new(), streak: 0 };
+
+    let active = cache
+        .plans
+        .iter()
+        .filter(|plan| plan.status == PlanStatus::Active && !plan.paused_on(today));
+    for plan in active {
+        let Some(cadence) = plan_cadence(&plan.goal, plan.cadence.as_deref()) else {
+            continue;
+        };
+        let Some(activity) = plan_activity(&plan.goal) else {
+            continue;
+        };
+        if cadence.until.is_some_and(|until| until < today) {
+            continue;
+        }
+        let done: BTreeSet<NaiveDate> = logs
+            .iter()
+            .filter(|(_, logged_activity, text)| counts_towards(&activity, logged_activity, text))
+            .map(|(day, ..)| *day)
+            .collect();
+        if !scheduled(&cadence.recurrence, &done, today) {
+            continue;
+        }
+
+        let entry = DuePlan { id: plan.id, goal: plan.goal.clone(), schedule: cadence.recurrence.to_string() };
+        if done.contains(&today) { agenda.done.push(entry) } else { agenda.due.push(entry) }
+    }
+
+    let days: BTreeSet<NaiveDate> = logs.iter().map(|(day, ..)| *day).collect();
+    (agenda.streak, _) = charts::streaks(&days, today);
+    Ok(agenda)
+}
+
+/// Plans scheduled for `today` that haven't been logged yet.
+pub fn due_plans(cache: &Cache, today: NaiveDate) -> Result<Vec<DuePlan>> {
+    Ok(plans_today(cache, today)?.due)
+}
+
+/// Whether the schedule asks for it today, given the days it was done.
+fn scheduled(recurrence: &Recurrence, done: &BTreeSet<NaiveDate>, today: NaiveDate) -> bool {
+    match recurrence {
+        Recurrence::Daily => true,
+        Recurrence::OnDays { days } => days.contains(&today.weekday()),
+        Recurrence::EveryNDays { days } => done
+            .range(..today)
+            .next_back()
+            .is_none_or(|last| (today - *last).num_days() >= i64::from(*days)),
+        Recurrence::PerWeek { times } => {
+            let monday = today.week(Weekday::Mon).first_day();
+            (done.range(monday..today).count() as u32) < *times
+        }
+    }
+}
+
+/// Sessions a week the schedule asks for.
+pub fn weekly_target(recurrence: &Recurrence) -> u32 {
+    match recurrence {
+        Recurrence::Daily => 7,
+        Recurrence::OnDays { days } => days.len() as u32,
+        Recurrence::EveryNDays { days } => 7u32.div_ceil(*days),
+        Recurrence::PerWeek { times } => *times,
+    }
+}
+
+/// Current and longest runs of keeping the schedule: days for daily plans,
+/// scheduled days for weekday plans, weeks for weekly targets, and sessions no
+/// more than n days apart for every-n-days plans. A period that isn't over yet
+/// only breaks the run once it is.
+pub fn plan_streaks(recurrence: &Recurrence, done: &BTreeSet<NaiveDate>, today: NaiveDate) -> (u32, u32) {
+    let Some(first) = done.first().copied() else {
+        return (0, 0);
+    };
+    let days = || first.iter_days().take_while(|day| *day <= today);
+
+    // Whether each period from the first session on was kept, oldest first
+    let mut kept: Vec<bool> = match recurrence {
+        Recurrence::Daily => days().map(|day| done.contains(&day)).collect(),
+        Recurrence::OnDays { days: weekdays } => days()
+            .filter(|day| weekdays.contains(&day.weekday()))
+            .map(|day| done.contains(&day))
+            .collect(),
+        Recurrence::PerWeek { times } => days()
+            .filter(|day| *day == first || day.weekday() == Weekday::Mon)
+            .map(|day| {
+                let monday = day.week(Weekday::Mon).first_day();
+                done.range(monday..monday + Days::new(7)).count() as u32 >= *times
+            })
+            .collect(),
+        Recurrence::EveryNDays { days: gap } => {
+            let gap = i64::from(*gap);
+            let mut kept = Vec::new();
+            let mut last: Option<NaiveDate> = None;
+            for day in done.range(..=today) {
+                if last.is_some_and(|last| (*day - last).num_days() > gap) {
+                    kept.push(false);
+                }
+                kept.push(true);
+                last = Some(*day);
+            }
+            if last.is_some_and(|last| (today - last).num_days() > gap) {
+                kept.push(false);
+            }
+            kept
+        }
+    };
+
+    let current_period_open = match recurrence {
+        Recurrence::EveryNDays { .. } => false,
+        Recurrence::OnDays { days } => days.contains(&today.weekday()),
+        Recurrence::Daily | Recurrence::PerWeek { .. } => true,
+    };
+    if current_period_open && kept.last() == Some(&false) {
+        kept.pop();
+    }
+
+    let current = kept.iter().rev().take_while(|kept| **kept).count() as u32;
+    let best = kept
+        .split(|kept| !kept)
+        .map(|run| run.len() as u32)
+        .max()
+        .unwrap_or(0);
+    (current, best)
+}
+
+/// What `plan_streaks` counts for this schedule.
+pub fn streak_unit(recurrence: &Recurrence) -> &'static str {
+    match recurrence {
+        Recurrence::Daily => "day(s)",
+        Recurrence::OnDays { .. } => "scheduled day(s)",
+        Recurrence::PerWeek { .. } => "week(s)",
+        Recurrence::EveryNDays { .. } => "session(s)",
+    }
+}
+
+/// The local day, activity and text of every cached and queued log, without plan definitions.
+fn logged(cache: &Cache) -> Result<Vec<(NaiveDate, String, String)>> {
+    let server = cache
+        .occurrences
+        .iter()
+        .filter_map(|occ| Some((occ.time()?, occ.text.clone())));
+    let queued = queue::load_queue()?
+        .into_iter()
+        .map(|entry| (entry.occurred_at.unwrap_or(entry.captured_at), entry.raw_input));
+
+    Ok(server
+        .chain(queued)
+        .filter(|(_, text)| !text.contains("#plan"))
+        .map(|(time, text)| {
+            let activity = parse_log(&text)
+                .and_then(|parsed| parsed.activity)
+                .unwrap_or_else(|| text.to_lowercase());
+            (time.with_timezone(&Local).date_naive(), activity, text)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A Sunday.
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 10, 18).unwrap()
+    }
+
+    /// Days before `today()`.
+    fn days_ago(ago: &[u64]) -> BTreeSet<NaiveDate> {
+        ago.iter().map(|n| today() - Days::new(*n)).collect()
+    }
+
+    #[test]
+    fn daily_streaks() {
+        let done = days_ago(&[0, 1, 2, 4, 5, 6, 7]);
+        assert_eq!(plan_streaks(&Recurrence::Daily, &done, today()), (3, 4));
+        // Not done yet today doesn't break it
+        assert_eq!(plan_streaks(&Recurrence::Daily, &days_ago(&[1, 2]), today()), (2, 2));
+        assert_eq!(plan_streaks(&Recurrence::Daily, &days_ago(&[2, 3]), today()), (0, 2));
+        assert_eq!(plan_streaks(&Recurrence::Daily, &BTreeSet::new(), today()), (0, 0));
+    }
+
+    #[test]
+    fn weekday_streaks_skip_other_days() {
+        let recurrence = Recurrence::OnDays { days: vec![Weekday::Mon, Weekday::Wed, Weekday::Fri] };
+        // Fri 16th, Wed 14th, Mon 12th, then Fri 9th missed, Wed 7th done
+        let done = days_ago(&[2, 4, 6, 11]);
+        assert_eq!(plan_streaks(&recurrence, &done, today()), (3, 3));
+        // Missing Friday ends it, even on an unscheduled day
+        assert_eq!(plan_streaks(&recurrence, &days_ago(&[4, 6]), today()), (0, 2));
+    }
+
+    #[test]
+    fn weekly_streaks_count_weeks() {
+        let recurrence = Recurrence::PerWeek { times: 2 };
+        // This week (12th-18th) twice, last week twice, the week before once
+        let done = days_ago(&[0, 3, 7, 9, 15]);
+        assert_eq!(plan_streaks(&recurrence, &done, today()), (2, 2));
+        // This week is still open
+        assert_eq!(plan_streaks(&recurrence, &days_ago(&[3, 7, 9]), today()), (1, 1));
+    }
+
+    #[test]
+    fn every_n_days_streaks() {
+        let recurrence = Recurrence::EveryNDays { days: 3 };
+        assert_eq!(plan_streaks(&recurrence, &days_ago(&[1, 4, 7, 20, 23]), today()), (3, 3));
+        // Overdue
+        assert_eq!(plan_streaks(&recurrence, &days_ago(&[4, 7]), today()), (0, 2));
+    }
+
+    #[test]
+    fn counts_whole_words_only() {
+        assert!(counts_towards("run", "run", "5km run"));
+        assert!(counts_towards("run", "", "morning run, felt easy"));
+        assert!(counts_towards("pullup", "", "10 pullups #morning"));
+        assert!(counts_towards("push up", "", "3x10 push-ups"));
+        assert!(!counts_towards("run", "brunch", "brunch with friends"));
+        assert!(!counts_towards("push up", "", "push press, then sit ups"));
+    }
+
+    #[test]
+    fn weekly_targets() {
+        assert_eq!(weekly_target(&Recurrence::Daily), 7);
+        assert_eq!(weekly_target(&Recurrence::PerWeek { times: 3 }), 3);
+        assert_eq!(weekly_target(&Recurrence::OnDays { days: vec![Weekday::Tue, Weekday::Thu] }), 2);
+        assert_eq!(weekly_target(&Recurrence::EveryNDays { days: 2 }), 4);
+    }
+}
@@ -0,0 +1,415 @@
+use anyhow::Result;
+use chrono::{DateTime, SecondsFormat, Utc};
+use reqwest::{Method, RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::time::Duration;
+use uuid::Uuid;
+use crate::api::types::{
+    ConfirmRequest, ConfirmationResponse, LogRequest, OccurrencesResponse, Plan, PlanDetailResponse,
+    PlanPauseRequest, PlanUpdateRequest, PlansResponse, SuggestionsResponse, SummaryResponse,
+};
+use crate::auth::token_manager;
+use crate::config;
+
+const USER_AGENT: &str = concat!("imp/", env!("CARGO_PKG_VERSION"));
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const GET_RETRIES: u32 = 2;
+
+#[derive(Debug)]
+pub enum ApiError {
+    Network(reqwest::Error),
+    /// A success response whose body isn't what was expected. Asking again won't change it.
+    Decode(reqwest::Error),
+    Status { status: StatusCode, body: String },
+}
+
+impl ApiError {
+    /// Whether the same request may succeed later without any change.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            ApiError::Network(_) => true,
+            ApiError::Decode(_) => false,
+            ApiError::Status { status, .. } => {
+                status.is_server_error()
+                    || *status == StatusCode::UNAUTHORIZED
+                    || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+        }
+    }
+
+    pub fn is_unauthorized(&self) -> bool {
+        matches!(self, ApiError::Status { status, .. } if *status == StatusCode::UNAUTHORIZED)
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Network(e) => write!(f, "network error: {}", e),
+            ApiError::Decode(e) => write!(f, "unexpected response: {}", e),
+            ApiError::Status { status, body } => write!(f, "{} - {}", status, body),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl From<reqwest::Error> for ApiError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_decode() {
+            ApiError::Decode(e)
+        } else {
+            ApiError::Network(e)
+        }
+    }
+}
+
+/// What to ask `/view/occurrences` for; the default is everything.
+#[derive(Default)]
+pub struct OccurrenceQuery {
+    pub since: Option<DateTime<Utc>>,
+    /// Exclusive.
+    pub until: Option<DateTime<Utc>>,
+    /// The newest this many.
+    pub limit: Option<usize>,
+    /// Only what was added, edited or deleted since an earlier response's cursor.
+    pub cursor: Option<String>,
+}
+
+impl OccurrenceQuery {
+    fn params(&self) -> Vec<(&'static str, String)> {
+        let time = |t: &DateTime<Utc>| t.to_rfc3339_opts(SecondsFormat::Secs, true);
+        [
+            self.since.as_ref().map(|t| ("since", time(t))),
+            self.until.as_ref().map(|t| ("until", time(t))),
+            self.limit.map(|n| ("limit", n.to_string())),
+            self.cursor.clone().map(|c| ("cursor", c)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+/// Gets a new access token to replace the rejected one it's given.
+type Refresh = dyn Fn(String) -> Pin<Box<dyn Future<Output = Result<String>> + Send>> + Send + Sync;
+
+pub struct ApiClient {
+    http: reqwest::Client,
+    base_url: String,
+    /// Replaced when a 401 forces a refresh mid-command.
+    token: Mutex<String>,
+    refresh: Box<Refresh>,
+}
+
+impl ApiClient {
+    pub fn new(base_url: &str, token: String) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(REQUEST_TIMEOUT)
+            .build()?;
+
+        Ok(Self {
+            http,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            token: Mutex::new(token),
+            refresh: Box::new(|stale| Box::pin(async move { token_manager::refresh(&stale).await })),
+        })
+    }
+
+    #[cfg(test)]
+    fn with_refresh(mut self, refresh: impl Fn(String) -> Option<String> + Send + Sync + 'static) -> Self {
+        self.refresh = Box::new(move |stale| {
+            let fresh = refresh(stale);
+            Box::pin(async move { fresh.ok_or_else(|| anyhow::anyhow!("refresh refused")) })
+        });
+        self
+    }
+
+    /// Client for the configured backend using the signed-in user's token.
+    pub async fn authenticated() -> Result<Self> {
+        let token = token_manager::get_valid_token().await?;
+        Self::new(&config::get().api_url, token)
+    }
+
+    /// Logs an action and returns the created log's id when the server sends
+    /// one back. With a key, the server keeps only the first log sent with it.
+    pub async fn log(&self, request: &LogRequest, idempotency_key: Option<Uuid>) -> Result<Option<String>, ApiError> {
+        let mut builder = self.request(Method::POST, "/log").json(request);
+        if let Some(key) = idempotency_key {
+            builder = builder.header("Idempotency-Key", key.to_string());
+        }
+        let response = self.send(builder).await?;
+        let body: Value = response.json().await.unwrap_or(Value::Null);
+        Ok(match &body["id"] {
+            Value::String(id) => Some(id.clone()),
+            Value::Number(id) => Some(id.to_string()),
+            _ => None,
+        })
+    }
+
+    pub async fn confirmations(&self) -> Result<Vec<ConfirmationResponse>, ApiError> {
+        self.get("/confirm", &[]).await
+    }
+
+    pub async fn confirm(&self, request: &ConfirmRequest) -> Result<(), ApiError> {
+        self.send(self.request(Method::POST, "/confirm").json(request)).await?;
+        Ok(())
+    }
+
+    pub async fn summary(&self) -> Result<SummaryResponse, ApiError> {
+        self.get("/view", &[]).await
+    }
+
+    /// Occurrences matching `query`. Servers that don't support a parameter
+    /// ignore it, so callers filter the result again.
+    pub async fn occurrences(&self, query: &OccurrenceQuery) -> Result<OccurrencesResponse, ApiError> {
+        self.get("/view/occurrences", &query.params()).await
+    }
+
+    /// Active and paused plans, plus archived ones when `archived`.
+    pub async fn plans(&self, archived: bool) -> Result<PlansResponse, ApiError> {
+        let query: Vec<(&str, String)> = if archived { vec![("archived", "true".to_string())] } else { Vec::new() };
+        self.get("/plans", &query).await
+    }
+
+    pub async fn plan(&self, id: Uuid) -> Result<PlanDetailResponse, ApiError> {
+        self.get(&format!("/plans/{}", id), &[]).await
+    }
+
+    pub async fn update_plan(&self, id: Uuid, request: &PlanUpdateRequest) -> Result<Plan, ApiError> {
+        let response = self.send(self.request(Method::PATCH, &format!("/plans/{}", id)).json(request)).await?;
+        Ok(response.json().await?)
+    }
+
+    pub async fn pause_plan(&self, id: Uuid, request: &PlanPauseRequest) -> Result<Plan, ApiError> {
+        let response = self.send(self.request(Method::POST, &format!("/plans/{}/pause", id)).json(request)).await?;
+        Ok(response.json().await?)
+    }
+
+    pub async fn resume_plan(&self, id: Uuid) -> Result<Plan, ApiError> {
+        let response = self.send(self.request(Method::POST, &format!("/plans/{}/resume", id))).await?;
+        Ok(response.json().await?)
+    }
+
+    pub async fn archive_plan(&self, id: Uuid) -> Result<Plan, ApiError> {
+        let response = self.send(self.request(Method::POST, &format!("/plans/{}/archive", id))).await?;
+        Ok(response.json().await?)
+    }
+
+    /// Suggestions based on activity since `since`. Not every server has them,
+    /// so callers should treat any error as "none".
+    pub async fn suggestions(&self, since: DateTime<Utc>) -> Result<SuggestionsResponse, ApiError> {
+        self.get("/analyze", &[("since", since.to_rfc3339_opts(SecondsFormat::Secs, true))]).await
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.http.request(method, format!("{}{}", self.base_url, path))
+    }
+
+    /// GETs are idempotent, so transient failures are retried with a short backoff.
+    async fn get<T: DeserializeOwned>(&self, path: &str, query: &[(&str, String)]) -> Result<T, ApiError> {
+        let mut attempt = 0;
+        loop {
+            match self.send(self.request(Method::GET, path).query(query)).await {
+                Ok(response) => return Ok(response.json().await?),
+                Err(e) if attempt < GET_RETRIES && e.is_transient() && !e.is_unauthorized() => {
+                    attempt += 1;
+                    tokio::time::sleep(Duration::from_millis(300 * 2u64.pow(attempt))).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Sends with the current token. A 401 means it was revoked or expired
+    /// early, so the tokens are refreshed once and the request retried.
+    async fn send(&self, request: RequestBuilder) -> Result<reqwest::Response, ApiError> {
+        let retry = request.try_clone();
+        let token = self.token.lock().unwrap().clone();
+
+        match self.send_once(request.bearer_auth(&token)).await {
+            Err(e) if e.is_unauthorized() => {
+                let Some(retry) = retry else {
+                    return Err(e);
+                };
+                let Ok(fresh) = (self.refresh)(token).await else {
+                    return Err(e);
+                };
+                *self.token.lock().unwrap() = fresh.clone();
+                self.send_once(retry.bearer_auth(&fresh)).await
+            }
+            result => result,
+        }
+    }
+
+    async fn send_once(&self, request: RequestBuilder) -> Result<reqwest::Response, ApiError> {
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(ApiError::Status { status, body });
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ImpError;
+    use crate::test_support::{MockServer, Response};
+
+    fn client(server: &MockServer) -> ApiClient {
+        ApiClient::new(&server.url, "old".to_string()).unwrap().with_refresh(|stale| {
+            assert_eq!(stale, "old");
+            Some("new".to_string())
+        })
+    }
+
+    fn summary() -> Response {
+        Response::json(200, r#"{"summary": "3 runs this week"}"#)
+    }
+
+    #[tokio::test]
+    async fn refreshes_and_retries_after_401() {
+        let server = MockServer::start(|request, _| match request.header("authorization") {
+            Some("Bearer new") => summary(),
+            _ => Response::json(401, "{}"),
+        })
+        .await;
+        let api = client(&server);
+
+        assert_eq!(api.summary().await.unwrap().summary, "3 runs this week");
+        // The fresh token is kept for later requests
+        api.summary().await.unwrap();
+
+        let auth: Vec<_> = server.requests().iter().map(|r| r.header("authorization").unwrap().to_string()).collect();
+        assert_eq!(auth, ["Bearer old", "Bearer new", "Bearer new"]);
+    }
+
+    #[tokio::test]
+    async fn refresh_failure_keeps_the_401() {
+        let server = MockServer::start(|_, _| Response::json(401, "expired")).await;
+        let api = ApiClient::new(&server.url, "old".to_string()).unwrap().with_refresh(|_| None);
+
+        let error = api.summary().await.err().unwrap();
+        assert!(error.is_unauthorized());
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors() {
+        let server =
+            MockServer::start(|_, index| if index < 2 { Response::json(503, "busy") } else { summary() }).await;
+
+        client(&server).summary().await.unwrap();
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_persistent_server_errors() {
+        let server = MockServer::start(|_, _| Response::json(500, "broken")).await;
+
+        let error = client(&server).summary().await.err().unwrap();
+        assert!(error.is_transient());
+        assert!(matches!(ImpError::from(error), ImpError::ServerRejected(500, _)));
+        assert_eq!(server.requests().len(), 1 + GET_RETRIES as usize);
+    }
+
+    #[tokio::test]
+    async fn logs_send_their_idempotency_key() {
+        let server = MockServer::start(|_, _| Response::json(200, r#"{"id": "abc"}"#)).await;
+        let request = LogRequest {
+            raw_input: "5 pushups".to_string(),
+            occurred_at: None,
+            note: None,
+            parsed: None,
+            cadence: None,
+        };
+        let key = Uuid::new_v4();
+
+        let id = client(&server).log(&request, Some(key)).await.unwrap();
+        assert_eq!(id.as_deref(), Some("abc"));
+        client(&server).log(&request, None).await.unwrap();
+
+        let keys: Vec<_> = server.requests().iter().map(|r| r.header("idempotency-key").map(str::to_string)).collect();
+        assert_eq!(keys, [Some(key.to_string()), None]);
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_permanent() {
+        let server = MockServer::start(|_, _| Response::json(400, "bad request")).await;
+
+        let error = client(&server).summary().await.err().unwrap();
+        assert!(!error.is_transient());
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn throttling_is_transient() {
+        let server = MockServer::start(|_, _| Response::json(429, "slow down")).await;
+        let api = client(&server);
+
+        let error = api.confirm(&ConfirmRequest { confirmation_id: Uuid::nil(), raw_input: "yes".into() }).await.err().unwrap();
+        assert!(error.is_transient());
+        assert!(matches!(ImpError::from(error), ImpError::RateLimited));
+    }
+
+    #[tokio::test]
+    async fn unreadable_bodies_are_permanent() {
+        let server = MockServer::start(|_, _| Response::json(200, "<html>maintenance</html>")).await;
+
+        let error = client(&server).summary().await.err().unwrap();
+        assert!(matches!(error, ApiError::Decode(_)));
+        assert!(!error.is_transient());
+        assert!(matches!(ImpError::from(error), ImpError::BadResponse(_)));
+        // Not retried, unlike a network error
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn mutators_report_unreadable_bodies() {
+        let server = MockServer::start(|_, _| Response::json(200, r#"{"unexpected": true}"#)).await;
+
+        let error = client(&server).resume_plan(Uuid::nil()).await.err().unwrap();
+        assert!(matches!(error, ApiError::Decode(_)));
+    }
+
+    #[tokio::test]
+    async fn sends_occurrence_filters_as_query_params() {
+        let server = MockServer::start(|_, _| Response::json(200, r#"{"occurrences": []}"#)).await;
+        let query = OccurrenceQuery {
+            since: "2026-10-12T00:00:00Z".parse().ok(),
+            until: "2026-10-19T00:00:00Z".parse().ok(),
+            limit: Some(20),
+            cursor: None,
+        };
+        client(&server).occurrences(&query).await.unwrap();
+        client(&server).occurrences(&OccurrenceQuery::default()).await.unwrap();
+
+        let paths: Vec<String> = server.requests().into_iter().map(|r| r.path).collect();
+        assert_eq!(
+            paths,
+            [
+                "/view/occurrences?since=2026-10-12T00%3A00%3A00Z&until=2026-10-19T00%3A00%3A00Z&limit=20",
+                "/view/occurrences",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn unreachable_servers_are_transient() {
+        let api = ApiClient::new("http://127.0.0.1:9", "old".to_string()).unwrap();
+        let error = api.confirm(&ConfirmRequest { confirmation_id: Uuid::nil(), raw_input: "yes".into() }).await.err().unwrap();
+        assert!(matches!(error, ApiError::Network(_)));
+        assert!(error.is_transient());
+    }
+}
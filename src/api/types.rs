@@ -0,0 +1,170 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::parse::cadence::Cadence;
+use crate::parse::log::ParsedLog;
+
+#[derive(Serialize)]
+pub struct LogRequest {
+    pub raw_input: String,
+    /// When the action happened, if not now (e.g. replayed from the offline queue).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub occurred_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// How the client read `raw_input`, so the server can start from it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parsed: Option<ParsedLog>,
+    /// The schedule of a `#plan`, as confirmed by the user.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cadence: Option<Cadence>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ConfirmationResponse {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub log_id: Uuid,
+    pub prompt_shown: String,
+    pub choices: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct ConfirmRequest {
+    pub confirmation_id: Uuid,
+    pub raw_input: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SummaryResponse {
+    pub summary: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct OccurrenceItem {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub timestamp: String,
+    pub text: String,
+    /// Logged on this machine but not delivered yet; never set by the server.
+    #[serde(default)]
+    pub queued: bool,
+}
+
+impl OccurrenceItem {
+    pub fn time(&self) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(&self.timestamp).ok().map(|t| t.with_timezone(&Utc))
+    }
+
+    /// Identifies the occurrence across syncs: its id, or its time and text
+    /// when the server sends no ids.
+    pub fn key(&self) -> String {
+        match &self.id {
+            Some(id) => id.clone(),
+            None => format!("{}\n{}", self.timestamp, self.text),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct OccurrencesResponse {
+    /// New or edited since the cursor that was sent, or the whole history.
+    pub occurrences: Vec<OccurrenceItem>,
+    /// Ids deleted since the cursor that was sent.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deleted: Vec<String>,
+    /// Where to continue from next time. Without one, `occurrences` is the whole history.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PlanStatus {
+    #[default]
+    Active,
+    Paused,
+    Archived,
+}
+
+/// How far along a plan is in its current period, e.g. 3 of 5 this week.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PlanProgress {
+    pub done: u32,
+    pub target: u32,
+    pub period: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Plan {
+    pub id: Uuid,
+    pub goal: String,
+    #[serde(default)]
+    pub status: PlanStatus,
+    /// How often it's meant to happen, as the server reads it, e.g. "every day".
+    #[serde(default)]
+    pub cadence: Option<String>,
+    #[serde(default)]
+    pub target: Option<u32>,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub paused_from: Option<NaiveDate>,
+    /// Last paused day; open-ended when unset.
+    #[serde(default)]
+    pub paused_until: Option<NaiveDate>,
+    #[serde(default)]
+    pub progress: Option<PlanProgress>,
+}
+
+impl Plan {
+    /// Whether the plan is on hold that day. A paused plan without dates is paused indefinitely.
+    pub fn paused_on(&self, day: NaiveDate) -> bool {
+        match (self.paused_from, self.paused_until) {
+            (None, None) => self.status == PlanStatus::Paused,
+            (from, until) => from.is_none_or(|from| day >= from) && until.is_none_or(|until| day <= until),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PlansResponse {
+    pub plans: Vec<Plan>,
+}
+
+/// A plan with the occurrences counted towards it, oldest first.
+#[derive(Serialize, Deserialize)]
+pub struct PlanDetailResponse {
+    pub plan: Plan,
+    #[serde(default)]
+    pub occurrences: Vec<OccurrenceItem>,
+}
+
+#[derive(Serialize, Default)]
+pub struct PlanUpdateRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub goal: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<u32>,
+}
+
+#[derive(Serialize)]
+pub struct PlanPauseRequest {
+    pub from: NaiveDate,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub until: Option<NaiveDate>,
+}
+
+/// Advice computed by the server, merged into `imp analyze`.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Suggestion {
+    pub text: String,
+    /// 1 is most urgent.
+    #[serde(default)]
+    pub priority: Option<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SuggestionsResponse {
+    pub suggestions: Vec<Suggestion>,
+}
@@ -1,19 +1,93 @@
 use aws_config::{BehaviorVersion, Region, defaults};
-use aws_sdk_cognitoidentityprovider::{Client, types::{AuthFlowType, ChallengeNameType, AttributeType}};
-use crate::auth::types::Tokens;
-use anyhow::{Context, Result};
-
-pub const REGION: &str = "us-east-1";
-pub const CLIENT_ID: &str = "6tlohqsfgoqiehi7q6027a3rl3";
+use aws_sdk_cognitoidentityprovider::error::{ProvideErrorMetadata, SdkError};
+use aws_sdk_cognitoidentityprovider::{Client, types::{AuthFlowType, AuthenticationResultType, ChallengeNameType, AttributeType, DeviceRememberedStatusType, DeviceType}};
+use aws_smithy_types::error::display::DisplayErrorContext;
+use aws_smithy_types::{Document, Number};
+use chrono::DateTime;
+use serde_json::Value;
+use std::collections::HashMap;
+use crate::auth::types::{Device, Passkey, Tokens};
+use crate::config;
+use crate::error::ImpError;
+use anyhow::{Context, Result, bail};
 
 pub enum OtpResult {
     Session(String),
     NeedsConfirmation { session: String },
 }
 
+fn tokens_from(auth_result: &AuthenticationResultType) -> Result<Tokens> {
+    Ok(Tokens {
+        access_token: auth_result.access_token().context("No access token")?.to_string(),
+        id_token: auth_result.id_token().context("No ID token")?.to_string(),
+        refresh_token: auth_result.refresh_token().context("No refresh token")?.to_string(),
+        device_key: auth_result
+            .new_device_metadata()
+            .and_then(|m| m.device_key())
+            .map(str::to_string),
+    })
+}
+
+/// Maps Cognito failures users can act on to an `ImpError`, and everything
+/// else to a one-line message instead of the SDK's debug output.
+/// `NotAuthorizedException` depends on the call, so callers say what it means.
+fn classify<E, R>(action: &str, e: SdkError<E, R>, not_authorized: Option<ImpError>) -> anyhow::Error
+where
+    E: ProvideErrorMetadata + std::error::Error + Send + Sync + 'static,
+    R: std::fmt::Debug + Send + Sync + 'static,
+{
+    if matches!(e, SdkError::DispatchFailure(_) | SdkError::TimeoutError(_)) {
+        return ImpError::NetworkUnavailable.into();
+    }
+
+    let mapped = match e.code() {
+        Some("CodeMismatchException") => Some(ImpError::InvalidOtp),
+        Some("ExpiredCodeException") => Some(ImpError::ExpiredOtp),
+        Some("TooManyRequestsException" | "LimitExceededException" | "TooManyFailedAttemptsException") => {
+            Some(ImpError::RateLimited)
+        }
+        Some("UserNotConfirmedException") => Some(ImpError::UserNotConfirmed),
+        Some("NotAuthorizedException") => not_authorized,
+        _ => None,
+    };
+
+    match (mapped, e.message()) {
+        (Some(error), _) => error.into(),
+        (None, Some(message)) => anyhow::anyhow!("{}: {}", action, message),
+        (None, None) => anyhow::anyhow!("{}: {}", action, DisplayErrorContext(&e)),
+    }
+}
+
+/// For calls made with the user's tokens, where being refused means they were revoked or expired.
+fn cognito_error<E, R>(action: &str, e: SdkError<E, R>) -> anyhow::Error
+where
+    E: ProvideErrorMetadata + std::error::Error + Send + Sync + 'static,
+    R: std::fmt::Debug + Send + Sync + 'static,
+{
+    classify(action, e, Some(ImpError::NotLoggedIn))
+}
+
+/// For signing in, where Cognito's own message ("User is disabled.") says why it refused.
+fn sign_in_error<E, R>(action: &str, e: SdkError<E, R>) -> anyhow::Error
+where
+    E: ProvideErrorMetadata + std::error::Error + Send + Sync + 'static,
+    R: std::fmt::Debug + Send + Sync + 'static,
+{
+    classify(action, e, None)
+}
+
+/// For answering an email code, where the session being refused is the code's.
+fn otp_error<E, R>(action: &str, e: SdkError<E, R>) -> anyhow::Error
+where
+    E: ProvideErrorMetadata + std::error::Error + Send + Sync + 'static,
+    R: std::fmt::Debug + Send + Sync + 'static,
+{
+    classify(action, e, Some(ImpError::ExpiredOtp))
+}
+
 async fn get_aws_client() -> Client {
     let config = defaults(BehaviorVersion::latest())
-        .region(Region::new(REGION))
+        .region(Region::new(config::get().region()))
         .load()
         .await;
     Client::new(&config)
@@ -25,7 +99,7 @@ pub async fn send_otp(email: &str) -> Result<OtpResult> {
     // Try signup first (like allinloop)
     let signup_result = client
         .sign_up()
-        .client_id(CLIENT_ID)
+        .client_id(&config::get().client_id)
         .username(email)
         .user_attributes(
             AttributeType::builder()
@@ -47,18 +121,17 @@ pub async fn send_otp(email: &str) -> Result<OtpResult> {
             Ok(OtpResult::NeedsConfirmation { session })
         }
         Err(e) => {
-            let error_str = format!("{:?}", e);
-            if error_str.contains("UsernameExistsException") {
+            if e.code() == Some("UsernameExistsException") {
                 // User exists - initiate auth
                 let auth_response = client
                     .initiate_auth()
-                    .client_id(CLIENT_ID)
+                    .client_id(&config::get().client_id)
                     .auth_flow(AuthFlowType::UserAuth)
                     .auth_parameters("USERNAME", email)
                     .auth_parameters("PREFERRED_CHALLENGE", "EMAIL_OTP")
                     .send()
                     .await
-                    .map_err(|e| anyhow::anyhow!("Failed to send OTP: {:?}", e))?;
+                    .map_err(|e| sign_in_error("Failed to send OTP", e))?;
 
                 let session = auth_response
                     .session()
@@ -66,7 +139,7 @@ pub async fn send_otp(email: &str) -> Result<OtpResult> {
                     .to_string();
                 Ok(OtpResult::Session(session))
             } else {
-                Err(anyhow::anyhow!("Failed to sign up: {:?}", e))
+                Err(sign_in_error("Failed to sign up", e))
             }
         }
     }
@@ -77,13 +150,13 @@ pub async fn confirm_signup_and_auth(email: &str, code: &str, session: &str) ->
 
     let response = client
         .confirm_sign_up()
-        .client_id(CLIENT_ID)
+        .client_id(&config::get().client_id)
         .username(email)
         .confirmation_code(code)
         .session(session)
         .send()
         .await
-        .map_err(|e| anyhow::anyhow!("Failed to confirm signup: {:?}", e))?;
+        .map_err(|e| otp_error("Failed to confirm signup", e))?;
 
     let auth_session = response
         .session()
@@ -92,23 +165,19 @@ pub async fn confirm_signup_and_auth(email: &str, code: &str, session: &str) ->
     // Continue auth with the session from confirmation
     let auth_response = client
         .initiate_auth()
-        .client_id(CLIENT_ID)
+        .client_id(&config::get().client_id)
         .auth_flow(AuthFlowType::UserAuth)
         .auth_parameters("USERNAME", email)
         .session(auth_session)
         .send()
         .await
-        .map_err(|e| anyhow::anyhow!("Failed to continue auth: {:?}", e))?;
+        .map_err(|e| sign_in_error("Failed to continue auth", e))?;
 
     let auth_result = auth_response
         .authentication_result()
         .context("No authentication result")?;
 
-    Ok(Tokens {
-        access_token: auth_result.access_token().context("No access token")?.to_string(),
-        id_token: auth_result.id_token().context("No ID token")?.to_string(),
-        refresh_token: auth_result.refresh_token().context("No refresh token")?.to_string(),
-    })
+    tokens_from(auth_result)
 }
 
 pub async fn verify_otp(
@@ -119,56 +188,52 @@ pub async fn verify_otp(
     let response = get_aws_client()
         .await
         .respond_to_auth_challenge()
-        .client_id(CLIENT_ID)
+        .client_id(&config::get().client_id)
         .challenge_name(ChallengeNameType::EmailOtp)
         .session(session)
         .challenge_responses("EMAIL_OTP_CODE", code)
         .challenge_responses("USERNAME", email)
         .send()
         .await
-        .map_err(|e| anyhow::anyhow!("Failed to verify OTP: {:?}", e))?;
+        .map_err(|e| otp_error("Failed to verify OTP", e))?;
 
     let auth_result = response
         .authentication_result()
         .context("No authentication result")?;
 
-    Ok(Tokens {
-        access_token: auth_result.access_token().context("No access token")?.to_string(),
-        id_token: auth_result.id_token().context("No ID token")?.to_string(),
-        refresh_token: auth_result.refresh_token().context("No Refresh Token")?.to_string(),
-    })
+    tokens_from(auth_result)
 }
 
-pub async fn refresh_tokens(refresh_token: &str) -> Result<Tokens> {
+pub async fn refresh_tokens(refresh_token: &str, device_key: Option<&str>) -> Result<Tokens> {
     let response = get_aws_client()
         .await
         .get_tokens_from_refresh_token()
-        .client_id(CLIENT_ID)
+        .client_id(&config::get().client_id)
         .refresh_token(refresh_token)
+        .set_device_key(device_key.map(str::to_string))
         .send()
         .await
-        .map_err(|e| anyhow::anyhow!("Failed to refresh tokens: {:?}", e))?;
+        .map_err(|e| cognito_error("Failed to refresh tokens", e))?;
 
     let auth_result = response
         .authentication_result()
         .context("No authentication result")?;
 
-    Ok(Tokens {
-        access_token: auth_result.access_token().context("No access token")?.to_string(),
-        id_token: auth_result.id_token().context("No ID token")?.to_string(),
-        refresh_token: auth_result.refresh_token().context("No Refresh Token")?.to_string(),
-    })
+    // Refreshing doesn't issue a new device, so keep the one we had
+    let mut tokens = tokens_from(auth_result)?;
+    tokens.device_key = tokens.device_key.or(device_key.map(str::to_string));
+    Ok(tokens)
 }
 
 pub async fn logout(refresh_token: &str) -> Result<()> {
     get_aws_client()
         .await
         .revoke_token()
-        .client_id(CLIENT_ID)
+        .client_id(&config::get().client_id)
         .token(refresh_token)
         .send()
         .await
-        .context("Failed to logout")?;
+        .map_err(|e| cognito_error("Failed to logout", e))?;
 
     Ok(())
 }
@@ -180,7 +245,268 @@ pub async fn global_logout(access_token: &str) -> Result<()> {
         .access_token(access_token)
         .send()
         .await
-        .context("Failed to logout globally")?;
+        .map_err(|e| cognito_error("Failed to logout globally", e))?;
+
+    Ok(())
+}
+
+/// Confirms a newly issued device so it shows up in the user's device list.
+pub async fn remember_device(access_token: &str, device_key: &str, device_name: &str) -> Result<()> {
+    let client = get_aws_client().await;
+
+    let response = client
+        .confirm_device()
+        .access_token(access_token)
+        .device_key(device_key)
+        .device_name(device_name)
+        .send()
+        .await
+        .map_err(|e| cognito_error("Failed to confirm device", e))?;
+
+    // Pools set to "user opt-in" only remember devices that ask to be remembered
+    if response.user_confirmation_necessary() {
+        client
+            .update_device_status()
+            .access_token(access_token)
+            .device_key(device_key)
+            .device_remembered_status(DeviceRememberedStatusType::Remembered)
+            .send()
+            .await
+            .map_err(|e| cognito_error("Failed to remember device", e))?;
+    }
+
+    Ok(())
+}
+
+fn device_from(device: &DeviceType, current_key: Option<&str>) -> Option<Device> {
+    let device_key = device.device_key()?.to_string();
+    let attribute = |name: &str| {
+        device
+            .device_attributes()
+            .iter()
+            .find(|a| a.name() == name)
+            .and_then(|a| a.value())
+            .map(str::to_string)
+    };
+
+    Some(Device {
+        current: current_key == Some(device_key.as_str()),
+        name: attribute("device_name"),
+        last_ip: attribute("last_ip_used"),
+        last_authenticated: device
+            .device_last_authenticated_date()
+            .and_then(|d| DateTime::from_timestamp(d.secs(), 0)),
+        device_key,
+    })
+}
+
+pub async fn list_devices(access_token: &str, current_key: Option<&str>) -> Result<Vec<Device>> {
+    let client = get_aws_client().await;
+    let mut devices = Vec::new();
+    let mut pagination_token = None;
+
+    loop {
+        let response = client
+            .list_devices()
+            .access_token(access_token)
+            .limit(60)
+            .set_pagination_token(pagination_token)
+            .send()
+            .await
+            .map_err(|e| cognito_error("Failed to list devices", e))?;
+
+        devices.extend(response.devices().iter().filter_map(|d| device_from(d, current_key)));
+
+        match response.pagination_token() {
+            Some(token) => pagination_token = Some(token.to_string()),
+            None => break,
+        }
+    }
+
+    Ok(devices)
+}
+
+pub async fn forget_device(access_token: &str, device_key: &str) -> Result<()> {
+    get_aws_client()
+        .await
+        .forget_device()
+        .access_token(access_token)
+        .device_key(device_key)
+        .send()
+        .await
+        .map_err(|e| cognito_error("Failed to forget device", e))?;
+
+    Ok(())
+}
+
+fn to_document(value: &Value) -> Document {
+    match value {
+        Value::Null => Document::Null,
+        Value::Bool(b) => Document::Bool(*b),
+        Value::Number(n) => match (n.as_u64(), n.as_i64()) {
+            (Some(u), _) => Document::Number(Number::PosInt(u)),
+            (None, Some(i)) => Document::Number(Number::NegInt(i)),
+            _ => Document::Number(Number::Float(n.as_f64().unwrap_or_default())),
+        },
+        Value::String(s) => Document::String(s.clone()),
+        Value::Array(items) => Document::Array(items.iter().map(to_document).collect()),
+        Value::Object(map) => Document::Object(
+            map.iter().map(|(k, v)| (k.clone(), to_document(v))).collect::<HashMap<_, _>>(),
+        ),
+    }
+}
+
+fn from_document(document: &Document) -> Value {
+    match document {
+        Document::Null => Value::Null,
+        Document::Bool(b) => Value::Bool(*b),
+        Document::Number(Number::PosInt(u)) => Value::from(*u),
+        Document::Number(Number::NegInt(i)) => Value::from(*i),
+        Document::Number(Number::Float(f)) => Value::from(*f),
+        Document::String(s) => Value::String(s.clone()),
+        Document::Array(items) => Value::Array(items.iter().map(from_document).collect()),
+        Document::Object(map) => Value::Object(
+            map.iter().map(|(k, v)| (k.clone(), from_document(v))).collect(),
+        ),
+    }
+}
+
+/// Returns the WebAuthn credential creation options for a new passkey.
+pub async fn start_passkey_registration(access_token: &str) -> Result<Value> {
+    let response = get_aws_client()
+        .await
+        .start_web_authn_registration()
+        .access_token(access_token)
+        .send()
+        .await
+        .map_err(|e| cognito_error("Failed to start passkey registration", e))?;
+
+    Ok(from_document(response.credential_creation_options()))
+}
+
+pub async fn complete_passkey_registration(access_token: &str, credential: &Value) -> Result<()> {
+    get_aws_client()
+        .await
+        .complete_web_authn_registration()
+        .access_token(access_token)
+        .credential(to_document(credential))
+        .send()
+        .await
+        .map_err(|e| cognito_error("Failed to register passkey", e))?;
 
     Ok(())
 }
+
+pub async fn list_passkeys(access_token: &str) -> Result<Vec<Passkey>> {
+    let client = get_aws_client().await;
+    let mut passkeys = Vec::new();
+    let mut next_token = None;
+
+    loop {
+        let response = client
+            .list_web_authn_credentials()
+            .access_token(access_token)
+            .set_next_token(next_token)
+            .send()
+            .await
+            .map_err(|e| cognito_error("Failed to list passkeys", e))?;
+
+        passkeys.extend(response.credentials().iter().map(|c| Passkey {
+            credential_id: c.credential_id().to_string(),
+            name: c.friendly_credential_name().to_string(),
+            rp_id: c.relying_party_id().to_string(),
+            attachment: c.authenticator_attachment().map(str::to_string),
+            created_at: DateTime::from_timestamp(c.created_at().secs(), 0),
+            on_this_machine: false,
+        }));
+
+        match response.next_token() {
+            Some(token) => next_token = Some(token.to_string()),
+            None => break,
+        }
+    }
+
+    Ok(passkeys)
+}
+
+pub async fn delete_passkey(access_token: &str, credential_id: &str) -> Result<()> {
+    get_aws_client()
+        .await
+        .delete_web_authn_credential()
+        .access_token(access_token)
+        .credential_id(credential_id)
+        .send()
+        .await
+        .map_err(|e| cognito_error("Failed to delete passkey", e))?;
+
+    Ok(())
+}
+
+/// Starts a passkey sign-in and returns the session with the WebAuthn request options.
+pub async fn start_passkey_auth(username: &str) -> Result<(String, Value)> {
+    let client = get_aws_client().await;
+
+    let response = client
+        .initiate_auth()
+        .client_id(&config::get().client_id)
+        .auth_flow(AuthFlowType::UserAuth)
+        .auth_parameters("USERNAME", username)
+        .auth_parameters("PREFERRED_CHALLENGE", "WEB_AUTHN")
+        .send()
+        .await
+        .map_err(|e| sign_in_error("Failed to start passkey sign-in", e))?;
+
+    let mut challenge = response.challenge_name().cloned();
+    let mut session = response.session().map(str::to_string);
+    let mut parameters = response.challenge_parameters().cloned().unwrap_or_default();
+
+    // Without a usable preference Cognito asks which challenge to answer
+    if challenge == Some(ChallengeNameType::SelectChallenge) {
+        let selected = client
+            .respond_to_auth_challenge()
+            .client_id(&config::get().client_id)
+            .challenge_name(ChallengeNameType::SelectChallenge)
+            .set_session(session)
+            .challenge_responses("USERNAME", username)
+            .challenge_responses("ANSWER", "WEB_AUTHN")
+            .send()
+            .await
+            .map_err(|e| sign_in_error("Failed to start passkey sign-in", e))?;
+        challenge = selected.challenge_name().cloned();
+        session = selected.session().map(str::to_string);
+        parameters = selected.challenge_parameters().cloned().unwrap_or_default();
+    }
+
+    if challenge != Some(ChallengeNameType::WebAuthn) {
+        bail!("Passkey sign-in isn't available for this account");
+    }
+
+    let options = parameters
+        .get("CREDENTIAL_REQUEST_OPTIONS")
+        .context("No passkey challenge returned")?;
+
+    Ok((
+        session.context("No session returned")?,
+        serde_json::from_str(options).context("Invalid passkey challenge")?,
+    ))
+}
+
+pub async fn verify_passkey(username: &str, session: &str, credential: &Value) -> Result<Tokens> {
+    let response = get_aws_client()
+        .await
+        .respond_to_auth_challenge()
+        .client_id(&config::get().client_id)
+        .challenge_name(ChallengeNameType::WebAuthn)
+        .session(session)
+        .challenge_responses("USERNAME", username)
+        .challenge_responses("CREDENTIAL", credential.to_string())
+        .send()
+        .await
+        .map_err(|e| sign_in_error("Failed to verify passkey", e))?;
+
+    let auth_result = response
+        .authentication_result()
+        .context("No authentication result")?;
+
+    tokens_from(auth_result)
+}
@@ -0,0 +1,435 @@
+//! File-backed software WebAuthn authenticator.
+//!
+//! Keeps ES256 passkeys for machines without a hardware key (headless Linux
+//! boxes, CI). What's safe to read without unlocking anything goes in
+//! `passkeys.json` in the profile directory; private keys go in the same store
+//! as tokens. Attestation is always "none".
+//!
+//! Only user presence is claimed, never user verification: nothing here
+//! checks who is at the keyboard.
+
+use anyhow::{Context, Result, bail};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::{DateTime, Utc};
+use ring::digest::{SHA256, digest};
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::signature::{ECDSA_P256_SHA256_ASN1_SIGNING, EcdsaKeyPair, KeyPair};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::fs;
+use std::path::PathBuf;
+use crate::auth::tokens;
+use crate::config;
+
+/// COSE algorithm identifier for ECDSA with P-256 and SHA-256.
+const COSE_ES256: i64 = -7;
+/// Software authenticators have no certified model, so the AAGUID is all zeros.
+const AAGUID: [u8; 16] = [0; 16];
+
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_ATTESTED_CREDENTIAL: u8 = 0x40;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct StoredCredential {
+    /// base64url, as the relying party sees it.
+    pub credential_id: String,
+    pub rp_id: String,
+    /// base64url user handle from the registration options.
+    pub user_handle: String,
+    pub user_name: String,
+    pub sign_count: u32,
+    pub created_at: DateTime<Utc>,
+}
+
+fn store_path() -> PathBuf {
+    config::profile_dir().join("passkeys.json")
+}
+
+pub fn load_credentials() -> Result<Vec<StoredCredential>> {
+    let path = store_path();
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let json = fs::read_to_string(&path).context("Failed to read passkeys")?;
+    serde_json::from_str(&json).context("Failed to parse passkeys")
+}
+
+fn save_credentials(credentials: &[StoredCredential]) -> Result<()> {
+    let path = store_path();
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(&path, serde_json::to_string_pretty(credentials)?).context("Failed to write passkeys")?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+    }
+
+    Ok(())
+}
+
+/// Stores a credential, with its `private_key` going to the token store.
+pub fn save_credential(credential: StoredCredential, private_key: &str) -> Result<()> {
+    let mut keys = tokens::load_passkey_keys()?;
+    keys.insert(credential.credential_id.clone(), private_key.to_string());
+    tokens::save_passkey_keys(&keys)?;
+
+    let mut credentials = load_credentials()?;
+    credentials.retain(|c| c.credential_id != credential.credential_id);
+    credentials.push(credential);
+    save_credentials(&credentials)
+}
+
+/// Drops a credential from the local store; returns whether it was there.
+pub fn remove_credential(credential_id: &str) -> Result<bool> {
+    let mut credentials = load_credentials()?;
+    let before = credentials.len();
+    credentials.retain(|c| c.credential_id != credential_id);
+    if credentials.len() == before {
+        return Ok(false);
+    }
+    save_credentials(&credentials)?;
+
+    let mut keys = tokens::load_passkey_keys()?;
+    if keys.remove(credential_id).is_some() {
+        tokens::save_passkey_keys(&keys)?;
+    }
+    Ok(true)
+}
+
+/// Creates a new passkey for `PublicKeyCredentialCreationOptions` and returns
+/// the registration response JSON, the credential and its private key as
+/// base64url PKCS#8. Nothing is stored, so callers can persist it once the
+/// relying party accepts it.
+pub fn make_credential(options: &Value) -> Result<(Value, StoredCredential, String)> {
+    let challenge = str_field(options, "challenge")?;
+    let rp_id = options["rp"]["id"].as_str().context("Registration options have no rp.id")?;
+    let user_handle = str_field(&options["user"], "id")?;
+    let user_name = options["user"]["name"].as_str().unwrap_or_default();
+
+    let supports_es256 = options["pubKeyCredParams"]
+        .as_array()
+        .is_some_and(|params| params.iter().any(|p| p["alg"].as_i64() == Some(COSE_ES256)));
+    if !supports_es256 {
+        bail!("The server doesn't accept ES256 passkeys");
+    }
+    check_user_verification(&options["authenticatorSelection"]["userVerification"])?;
+
+    let existing = load_credentials()?;
+    let excluded = options["excludeCredentials"].as_array().cloned().unwrap_or_default();
+    if excluded.iter().any(|e| existing.iter().any(|c| Some(c.credential_id.as_str()) == e["id"].as_str())) {
+        bail!("This machine already has a passkey for this account");
+    }
+
+    let rng = SystemRandom::new();
+    let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng)
+        .map_err(|_| anyhow::anyhow!("Failed to generate passkey"))?;
+    let key_pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng)
+        .map_err(|_| anyhow::anyhow!("Failed to load generated passkey"))?;
+
+    let mut credential_id = [0u8; 32];
+    rng.fill(&mut credential_id)
+        .map_err(|_| anyhow::anyhow!("Failed to generate credential id"))?;
+
+    let auth_data = registration_data(rp_id, &credential_id, key_pair.public_key().as_ref())?;
+    let attestation = attestation_object(&auth_data);
+
+    let client_data = client_data_json("webauthn.create", challenge, rp_id);
+    let id = URL_SAFE_NO_PAD.encode(credential_id);
+
+    let response = json!({
+        "id": id,
+        "rawId": id,
+        "type": "public-key",
+        "response": {
+            "clientDataJSON": URL_SAFE_NO_PAD.encode(&client_data),
+            "attestationObject": URL_SAFE_NO_PAD.encode(&attestation),
+            "transports": ["internal"],
+        },
+        "authenticatorAttachment": "platform",
+        "clientExtensionResults": {},
+    });
+
+    let stored = StoredCredential {
+        credential_id: id,
+        rp_id: rp_id.to_string(),
+        user_handle: user_handle.to_string(),
+        user_name: user_name.to_string(),
+        sign_count: 0,
+        created_at: Utc::now(),
+    };
+
+    Ok((response, stored, URL_SAFE_NO_PAD.encode(pkcs8.as_ref())))
+}
+
+/// Signs `PublicKeyCredentialRequestOptions` with a matching local passkey and
+/// returns the authentication response JSON.
+pub fn get_assertion(options: &Value) -> Result<Value> {
+    let challenge = str_field(options, "challenge")?;
+    let rp_id = str_field(options, "rpId")?;
+    check_user_verification(&options["userVerification"])?;
+    let allowed: Vec<&str> = options["allowCredentials"]
+        .as_array()
+        .map(|list| list.iter().filter_map(|c| c["id"].as_str()).collect())
+        .unwrap_or_default();
+
+    let mut credentials = load_credentials()?;
+    let credential = credentials
+        .iter_mut()
+        .find(|c| c.rp_id == rp_id && (allowed.is_empty() || allowed.contains(&c.credential_id.as_str())))
+        .context("No passkey for this account on this machine. Register one with: imp passkey register")?;
+
+    let keys = tokens::load_passkey_keys()?;
+    let private_key = keys.get(&credential.credential_id).with_context(|| {
+        format!("This passkey's key is missing from the {}. Register a new one with: imp passkey register", tokens::store_name())
+    })?;
+
+    let rng = SystemRandom::new();
+    let pkcs8 = URL_SAFE_NO_PAD.decode(private_key).context("Corrupt passkey store")?;
+    let key_pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &pkcs8, &rng)
+        .map_err(|_| anyhow::anyhow!("Corrupt passkey store"))?;
+
+    credential.sign_count += 1;
+    let auth_data = authenticator_data(rp_id, FLAG_USER_PRESENT, credential.sign_count);
+    let client_data = client_data_json("webauthn.get", challenge, rp_id);
+
+    let mut signed = auth_data.clone();
+    signed.extend_from_slice(digest(&SHA256, &client_data).as_ref());
+    let signature = key_pair
+        .sign(&rng, &signed)
+        .map_err(|_| anyhow::anyhow!("Failed to sign passkey challenge"))?;
+
+    let response = json!({
+        "id": credential.credential_id,
+        "rawId": credential.credential_id,
+        "type": "public-key",
+        "response": {
+            "clientDataJSON": URL_SAFE_NO_PAD.encode(&client_data),
+            "authenticatorData": URL_SAFE_NO_PAD.encode(&auth_data),
+            "signature": URL_SAFE_NO_PAD.encode(signature.as_ref()),
+            "userHandle": credential.user_handle,
+        },
+        "authenticatorAttachment": "platform",
+        "clientExtensionResults": {},
+    });
+
+    // Persist the counter so the relying party never sees it go backwards
+    save_credentials(&credentials)?;
+
+    Ok(response)
+}
+
+/// Refuses when the relying party insists on user verification, which can't
+/// honestly be claimed; "preferred" and "discouraged" go ahead without it.
+fn check_user_verification(requirement: &Value) -> Result<()> {
+    if requirement.as_str() == Some("required") {
+        bail!("The server requires user verification, which imp's software passkeys can't provide. Use a hardware key instead");
+    }
+    Ok(())
+}
+
+fn str_field<'a>(value: &'a Value, name: &str) -> Result<&'a str> {
+    value[name]
+        .as_str()
+        .with_context(|| format!("WebAuthn options have no {}", name))
+}
+
+fn client_data_json(kind: &str, challenge: &str, rp_id: &str) -> Vec<u8> {
+    json!({
+        "type": kind,
+        "challenge": challenge,
+        "origin": format!("https://{}", rp_id),
+        "crossOrigin": false,
+    })
+    .to_string()
+    .into_bytes()
+}
+
+fn authenticator_data(rp_id: &str, flags: u8, sign_count: u32) -> Vec<u8> {
+    let mut data = digest(&SHA256, rp_id.as_bytes()).as_ref().to_vec();
+    data.push(flags);
+    data.extend_from_slice(&sign_count.to_be_bytes());
+    data
+}
+
+/// Authenticator data for a new credential: the usual header followed by the
+/// attested credential data (AAGUID, id length, id, COSE public key).
+fn registration_data(rp_id: &str, credential_id: &[u8], public_key: &[u8]) -> Result<Vec<u8>> {
+    let mut data = authenticator_data(rp_id, FLAG_USER_PRESENT | FLAG_ATTESTED_CREDENTIAL, 0);
+    data.extend_from_slice(&AAGUID);
+    data.extend_from_slice(&(credential_id.len() as u16).to_be_bytes());
+    data.extend_from_slice(credential_id);
+    data.extend_from_slice(&cose_public_key(public_key)?);
+    Ok(data)
+}
+
+/// `{"fmt": "none", "attStmt": {}, "authData": ...}`
+fn attestation_object(auth_data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    cbor_head(&mut out, 5, 3);
+    cbor_text(&mut out, "fmt");
+    cbor_text(&mut out, "none");
+    cbor_text(&mut out, "attStmt");
+    cbor_head(&mut out, 5, 0);
+    cbor_text(&mut out, "authData");
+    cbor_bytes(&mut out, auth_data);
+    out
+}
+
+/// Encodes an uncompressed P-256 point as a COSE_Key map.
+fn cose_public_key(point: &[u8]) -> Result<Vec<u8>> {
+    if point.len() != 65 || point[0] != 0x04 {
+        bail!("Unexpected public key format");
+    }
+    let mut out = Vec::new();
+    cbor_head(&mut out, 5, 5);
+    cbor_int(&mut out, 1); // kty
+    cbor_int(&mut out, 2); // EC2
+    cbor_int(&mut out, 3); // alg
+    cbor_int(&mut out, COSE_ES256);
+    cbor_int(&mut out, -1); // crv
+    cbor_int(&mut out, 1); // P-256
+    cbor_int(&mut out, -2); // x
+    cbor_bytes(&mut out, &point[1..33]);
+    cbor_int(&mut out, -3); // y
+    cbor_bytes(&mut out, &point[33..]);
+    Ok(out)
+}
+
+// Just enough CBOR for attestation objects and COSE keys.
+
+fn cbor_head(out: &mut Vec<u8>, major: u8, value: u64) {
+    let major = major << 5;
+    match value {
+        0..=23 => out.push(major | value as u8),
+        24..=0xff => out.extend_from_slice(&[major | 24, value as u8]),
+        0x100..=0xffff => {
+            out.push(major | 25);
+            out.extend_from_slice(&(value as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(major | 26);
+            out.extend_from_slice(&(value as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(major | 27);
+            out.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+}
+
+fn cbor_int(out: &mut Vec<u8>, value: i64) {
+    if value >= 0 {
+        cbor_head(out, 0, value as u64);
+    } else {
+        cbor_head(out, 1, (-1 - value) as u64);
+    }
+}
+
+fn cbor_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    cbor_head(out, 2, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn cbor_text(out: &mut Vec<u8>, text: &str) {
+    cbor_head(out, 3, text.len() as u64);
+    out.extend_from_slice(text.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// SHA-256 of "example.com".
+    const RP_ID_HASH: &str = "a379a6f6eeafb9a55e378c118034e2751e682fab9f2d30ab13d2125586ce1947";
+
+    fn hex(text: &str) -> Vec<u8> {
+        let digits: Vec<u8> = text.bytes().filter(u8::is_ascii_hexdigit).collect();
+        digits
+            .chunks(2)
+            .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap())
+            .collect()
+    }
+
+    fn public_key() -> Vec<u8> {
+        [vec![0x04], vec![0x11; 32], vec![0x22; 32]].concat()
+    }
+
+    fn cose_key() -> Vec<u8> {
+        [
+            hex("a5 01 02 03 26 20 01 21 5820"),
+            vec![0x11; 32],
+            hex("22 5820"),
+            vec![0x22; 32],
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn assertion_authenticator_data() {
+        let data = authenticator_data("example.com", FLAG_USER_PRESENT, 7);
+        assert_eq!(data, [hex(RP_ID_HASH), hex("01 00000007")].concat());
+    }
+
+    #[test]
+    fn cose_key_encoding() {
+        assert_eq!(cose_public_key(&public_key()).unwrap(), cose_key());
+        assert!(cose_public_key(&public_key()[1..]).is_err());
+    }
+
+    #[test]
+    fn registration_authenticator_data() {
+        let data = registration_data("example.com", &[0xaa; 32], &public_key()).unwrap();
+        let expected = [
+            hex(RP_ID_HASH),
+            // Flags UP and AT, no UV; counter 0
+            hex("41 00000000"),
+            vec![0; 16],
+            hex("0020"),
+            vec![0xaa; 32],
+            cose_key(),
+        ]
+        .concat();
+        assert_eq!(data, expected);
+        assert_eq!(data.len(), 164);
+    }
+
+    #[test]
+    fn attestation_object_encoding() {
+        let data = registration_data("example.com", &[0xaa; 32], &public_key()).unwrap();
+        let expected = [
+            // {"fmt": "none", "attStmt": {}, "authData": h'...'} with a 164-byte string
+            hex("a3 63 666d74 64 6e6f6e65 67 61747453746d74 a0 68 6175746844617461 58a4"),
+            data.clone(),
+        ]
+        .concat();
+        assert_eq!(attestation_object(&data), expected);
+    }
+
+    #[test]
+    fn cbor_heads() {
+        let encode = |major, value| {
+            let mut out = Vec::new();
+            cbor_head(&mut out, major, value);
+            out
+        };
+        assert_eq!(encode(0, 23), hex("17"));
+        assert_eq!(encode(0, 24), hex("18 18"));
+        assert_eq!(encode(2, 255), hex("58 ff"));
+        assert_eq!(encode(2, 256), hex("59 0100"));
+        assert_eq!(encode(3, 0x1_0000), hex("7a 00010000"));
+        assert_eq!(encode(0, 0x1_0000_0000), hex("1b 0000000100000000"));
+
+        let mut negative = Vec::new();
+        cbor_int(&mut negative, COSE_ES256);
+        cbor_int(&mut negative, -25);
+        assert_eq!(negative, hex("26 38 18"));
+    }
+
+    #[test]
+    fn user_verification_is_never_claimed() {
+        assert!(check_user_verification(&json!("preferred")).is_ok());
+        assert!(check_user_verification(&Value::Null).is_ok());
+        assert!(check_user_verification(&json!("required")).is_err());
+    }
+}
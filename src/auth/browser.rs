@@ -0,0 +1,274 @@
+use anyhow::{Context, Result, bail};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use reqwest::Url;
+use ring::digest::{SHA256, digest};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Deserialize;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use crate::auth::types::Tokens;
+use crate::{config, output};
+
+/// Each must match a callback URL registered on the Cognito app client. The
+/// later ones are only used when another program holds the first.
+const CALLBACK_PORTS: [u16; 3] = [53682, 53683, 53684];
+const CALLBACK_PATH: &str = "/callback";
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(300);
+
+const DONE_MESSAGE: &str = "You can close this tab and return to the terminal.";
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    id_token: String,
+    refresh_token: String,
+}
+
+/// Whether there's likely a browser to open on this machine. A browser opened
+/// from an SSH session would be on the remote end, and headless Linux sessions
+/// have neither an X11 nor a Wayland display.
+pub fn browser_available() -> bool {
+    let var = |name| std::env::var_os(name).is_some();
+    if var("SSH_CONNECTION") || var("SSH_TTY") {
+        return false;
+    }
+    if cfg!(target_os = "linux") {
+        var("DISPLAY") || var("WAYLAND_DISPLAY")
+    } else {
+        true
+    }
+}
+
+fn random_token(rng: &SystemRandom) -> Result<String> {
+    let mut bytes = [0u8; 32];
+    rng.fill(&mut bytes)
+        .map_err(|_| anyhow::anyhow!("Failed to generate random value"))?;
+    Ok(URL_SAFE_NO_PAD.encode(bytes))
+}
+
+fn open_browser(url: &str) -> bool {
+    let mut command = if cfg!(windows) {
+        let mut c = Command::new("cmd");
+        c.args(["/C", "start", ""]);
+        c
+    } else if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else {
+        Command::new("xdg-open")
+    };
+
+    command
+        .arg(url)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+/// Signs in through the hosted login page using the authorization code flow
+/// with PKCE, receiving the code on a short-lived localhost listener. Returns
+/// `None` when the browser couldn't be opened.
+pub async fn login(auth_domain: &str) -> Result<Option<Tokens>> {
+    let auth_domain = auth_domain.trim_end_matches('/');
+    let client_id = &config::get().client_id;
+
+    let rng = SystemRandom::new();
+    let verifier = random_token(&rng)?;
+    let challenge = URL_SAFE_NO_PAD.encode(digest(&SHA256, verifier.as_bytes()));
+    let state = random_token(&rng)?;
+
+    let (listener, port) = bind_callback().await?;
+    let redirect_uri = format!("http://localhost:{}{}", port, CALLBACK_PATH);
+
+    let mut url = Url::parse(&format!("{}/oauth2/authorize", auth_domain)).context("Invalid auth_domain")?;
+    url.query_pairs_mut()
+        .append_pair("response_type", "code")
+        .append_pair("client_id", client_id)
+        .append_pair("redirect_uri", &redirect_uri)
+        .append_pair("scope", "openid email profile")
+        .append_pair("code_challenge", &challenge)
+        .append_pair("code_challenge_method", "S256")
+        .append_pair("state", &state);
+
+    if !open_browser(url.as_str()) {
+        return Ok(None);
+    }
+    output::progress("Opened your browser to sign in. Waiting...");
+
+    let code = tokio::time::timeout(LOGIN_TIMEOUT, wait_for_code(&listener, &state))
+        .await
+        .context("Timed out waiting for the browser sign-in")??;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/oauth2/token", auth_domain))
+        .form(&[
+            ("grant_type", "authorization_code"),
+            ("client_id", client_id.as_str()),
+            ("code", code.as_str()),
+            ("redirect_uri", redirect_uri.as_str()),
+            ("code_verifier", verifier.as_str()),
+        ])
+        .send()
+        .await
+        .context("Failed to exchange sign-in code")?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        bail!("Failed to exchange sign-in code: {} - {}", status, body);
+    }
+
+    let tokens: TokenResponse = response.json().await.context("Invalid token response")?;
+
+    Ok(Some(Tokens {
+        access_token: tokens.access_token,
+        id_token: tokens.id_token,
+        refresh_token: tokens.refresh_token,
+        device_key: None,
+    }))
+}
+
+/// Listens on the first free callback port.
+async fn bind_callback() -> Result<(TcpListener, u16)> {
+    for port in CALLBACK_PORTS {
+        match TcpListener::bind(("127.0.0.1", port)).await {
+            Ok(listener) => return Ok((listener, port)),
+            Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => continue,
+            Err(e) => return Err(e).with_context(|| format!("Failed to listen on localhost:{}", port)),
+        }
+    }
+    bail!(
+        "Ports {} are all in use, close whatever holds them or use: imp login",
+        CALLBACK_PORTS.map(|port| port.to_string()).join(", ")
+    )
+}
+
+/// A small HTML response for the browser tab.
+fn page(status: &str, heading: &str, message: &str) -> String {
+    let body = format!(
+        "<html><body style=\"font-family:sans-serif\"><h2>{}</h2><p>{}</p></body></html>",
+        heading, message
+    );
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+/// Serves requests until the login redirect arrives and returns its code.
+/// Redirects that don't carry this login's state get an error page and are
+/// otherwise ignored, so a stale tab can't end or hijack the login.
+async fn wait_for_code(listener: &TcpListener, expected_state: &str) -> Result<String> {
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+
+        // Anything can connect, so whatever isn't the redirect is turned away
+        let mut buffer = vec![0u8; 8192];
+        let Ok(read) = stream.read(&mut buffer).await else {
+            continue;
+        };
+        let request = String::from_utf8_lossy(&buffer[..read]);
+
+        // "GET /callback?code=...&state=... HTTP/1.1"
+        let Some(target) = request.lines().next().and_then(|line| line.split_whitespace().nth(1)) else {
+            continue;
+        };
+        let Ok(url) = Url::parse(&format!("http://localhost{}", target)) else {
+            let reply = page("400 Bad Request", "Sign-in failed", "Use the link from the terminal.");
+            stream.write_all(reply.as_bytes()).await.ok();
+            continue;
+        };
+        if url.path() != CALLBACK_PATH {
+            stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n").await.ok();
+            continue;
+        }
+
+        let param = |name: &str| url.query_pairs().find(|(k, _)| k == name).map(|(_, v)| v.into_owned());
+
+        if param("state").as_deref() != Some(expected_state) {
+            let reply = page(
+                "400 Bad Request",
+                "Sign-in failed",
+                "This sign-in link is out of date. Use the link from the terminal.",
+            );
+            stream.write_all(reply.as_bytes()).await.ok();
+            continue;
+        }
+        if let Some(error) = param("error") {
+            let reply = page("400 Bad Request", "Sign-in cancelled", "Return to the terminal to try again.");
+            stream.write_all(reply.as_bytes()).await.ok();
+            bail!("Sign-in was cancelled: {}", param("error_description").unwrap_or(error));
+        }
+        let Some(code) = param("code") else {
+            let reply = page("400 Bad Request", "Sign-in failed", "Return to the terminal to try again.");
+            stream.write_all(reply.as_bytes()).await.ok();
+            bail!("Sign-in response had no code");
+        };
+
+        stream.write_all(page("200 OK", "imp", DONE_MESSAGE).as_bytes()).await.ok();
+        return Ok(code);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpStream;
+
+    async fn get(port: u16, target: &str) -> String {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", target);
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn stale_state_gets_an_error_page_and_keeps_waiting() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let waiting = tokio::spawn(async move { wait_for_code(&listener, "expected").await });
+
+        let stale = get(port, "/callback?code=theirs&state=other").await;
+        assert!(stale.starts_with("HTTP/1.1 400"));
+        assert!(stale.contains("out of date"));
+        assert!(!waiting.is_finished());
+
+        let done = get(port, "/callback?code=ours&state=expected").await;
+        assert!(done.starts_with("HTTP/1.1 200"));
+        assert!(done.contains(DONE_MESSAGE));
+        assert_eq!(waiting.await.unwrap().unwrap(), "ours");
+    }
+
+    #[tokio::test]
+    async fn stray_requests_dont_end_the_login() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let waiting = tokio::spawn(async move { wait_for_code(&listener, "expected").await });
+
+        assert!(get(port, ":99999/").await.starts_with("HTTP/1.1 400"));
+        assert!(get(port, "/favicon.ico").await.starts_with("HTTP/1.1 404"));
+        assert!(!waiting.is_finished());
+
+        get(port, "/callback?code=ours&state=expected").await;
+        assert_eq!(waiting.await.unwrap().unwrap(), "ours");
+    }
+
+    #[tokio::test]
+    async fn cancelled_sign_in_gets_an_error_page() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let waiting = tokio::spawn(async move { wait_for_code(&listener, "expected").await });
+
+        let response = get(port, "/callback?error=access_denied&state=expected").await;
+        assert!(response.starts_with("HTTP/1.1 400"));
+        assert!(!response.contains(DONE_MESSAGE));
+        let error = waiting.await.unwrap().unwrap_err();
+        assert!(error.to_string().contains("access_denied"));
+    }
+}
@@ -1,5 +1,9 @@
+#[allow(clippy::module_inception)]
 pub mod auth;
+pub mod authenticator;
+pub mod browser;
 pub mod jwk;
 pub mod tokens;
+pub mod token_store;
 pub mod token_manager;
 pub mod types;
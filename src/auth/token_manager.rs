@@ -1,34 +1,90 @@
 use crate::auth::{auth, jwk, tokens};
-use anyhow::{bail, Result};
+use crate::config;
+use crate::error::ImpError;
+use anyhow::{bail, Context, Result};
+use std::fs::{File, OpenOptions, TryLockError};
+use std::time::{Duration, Instant};
 
-pub async fn validate_and_refresh() -> Result<String> {
-    let mut tokens = tokens::load_tokens()?;
+const LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Held while refreshing so concurrent `imp` processes don't each spend the
+/// refresh token and overwrite each other's tokens. Released on drop.
+struct RefreshLock {
+    _file: File,
+}
+
+impl RefreshLock {
+    async fn acquire() -> Result<Self> {
+        let path = config::profile_dir().join("refresh.lock");
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .context("Failed to open refresh lock")?;
+
+        let started = Instant::now();
+        loop {
+            match file.try_lock() {
+                Ok(()) => return Ok(Self { _file: file }),
+                Err(TryLockError::WouldBlock) if started.elapsed() < LOCK_TIMEOUT => {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+                Err(TryLockError::WouldBlock) => bail!("Timed out waiting for another imp to refresh tokens"),
+                Err(TryLockError::Error(e)) => return Err(e).context("Failed to lock tokens for refresh"),
+            }
+        }
+    }
+}
+
+/// Whether the token expires within the configured skew window (or can't be read).
+fn expires_soon(token: &str) -> bool {
+    let skew = config::get().refresh_skew_secs;
+    jwk::read_claims(token)
+        .map(|claims| claims.exp - skew <= chrono::Utc::now().timestamp())
+        .unwrap_or(true)
+}
+
+/// Refreshes the tokens unless another process already replaced `stale`.
+pub async fn refresh(stale: &str) -> Result<String> {
+    let _lock = RefreshLock::acquire().await?;
 
-    if jwk::validate_token(&tokens.access_token).await.is_ok() {
+    // Re-read under the lock: whoever held it before us may have refreshed already
+    let tokens = tokens::load_tokens()?;
+    if tokens.access_token != stale && !expires_soon(&tokens.access_token) {
         return Ok(tokens.access_token);
     }
 
-    tokens = auth::refresh_tokens(
+    let tokens = auth::refresh_tokens(
         &tokens.refresh_token,
-        // &tokens.device_key,
+        tokens.device_key.as_deref(),
     ).await?;
 
     tokens::save_tokens(&tokens)?;
 
-    if jwk::validate_token(&tokens.access_token).await.is_ok() {
+    Ok(tokens.access_token)
+}
+
+pub async fn validate_and_refresh() -> Result<String> {
+    let tokens = tokens::load_tokens()?;
+
+    if !expires_soon(&tokens.access_token) && jwk::validate_token(&tokens.access_token).await.is_ok() {
         return Ok(tokens.access_token);
     }
 
-    if jwk::fetch_jwks().await.is_ok() {
-        if jwk::validate_token(&tokens.access_token).await.is_ok() {
-            return Ok(tokens.access_token);
-        }
-    }
+    let access_token = refresh(&tokens.access_token).await?;
 
-    bail!("Token validation failed. Please login again with: imp login")
+    // Unknown signing keys are refetched inside validate_token, so a failure here is final
+    match jwk::validate_token(&access_token).await {
+        Ok(_) => Ok(access_token),
+        Err(e) => Err(anyhow::Error::new(ImpError::NotLoggedIn).context(format!("Token validation failed: {:#}", e))),
+    }
 }
 
+/// A missing or refused refresh token is already `NotLoggedIn`. Anything else
+/// (offline, throttled, a locked keyring, a wrong passphrase) is passed on as
+/// is, since signing in again wouldn't fix it.
 pub async fn get_valid_token() -> Result<String> {
-    validate_and_refresh().await
-        .map_err(|_| anyhow::anyhow!("Not logged in. Run: imp login"))
+    validate_and_refresh().await.context("Couldn't get a valid access token")
 }
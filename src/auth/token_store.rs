@@ -0,0 +1,353 @@
+use anyhow::{Context, Result, bail};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use ring::aead::{Aad, CHACHA20_POLY1305, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{IsTerminal, Write};
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+const KEYRING_SERVICE: &str = "imp";
+const PBKDF2_ITERATIONS: u32 = 310_000;
+const SALT_LEN: usize = 16;
+
+/// Somewhere a secret (tokens, passkey keys) can be kept between commands.
+pub trait TokenStore {
+    fn name(&self) -> &'static str;
+    fn load(&self) -> Result<Option<String>>;
+    fn save(&self, secret: &str) -> Result<()>;
+    fn delete(&self) -> Result<()>;
+
+    /// Whether the secret is stored, ideally without unlocking it.
+    fn exists(&self) -> bool {
+        self.load().is_ok_and(|secret| secret.is_some())
+    }
+}
+
+/// Plaintext JSON, readable only by the owner on Unix. The original format.
+pub struct FileStore {
+    pub path: PathBuf,
+}
+
+impl TokenStore for FileStore {
+    fn name(&self) -> &'static str {
+        "plaintext file"
+    }
+
+    fn exists(&self) -> bool {
+        self.path.exists()
+    }
+
+    fn load(&self) -> Result<Option<String>> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(fs::read_to_string(&self.path)?))
+    }
+
+    fn save(&self, secret: &str) -> Result<()> {
+        write_private(&self.path, secret.as_bytes())
+    }
+
+    fn delete(&self) -> Result<()> {
+        if self.path.exists() {
+            fs::remove_file(&self.path)?;
+        }
+        Ok(())
+    }
+}
+
+/// The OS keyring: Secret Service on Linux, Keychain on macOS, Credential Manager on Windows.
+pub struct KeyringStore {
+    /// The profile name for tokens, `<profile>/passkey-keys` for passkey keys.
+    pub account: String,
+}
+
+impl KeyringStore {
+    fn entry(&self) -> Result<keyring::Entry> {
+        keyring::Entry::new(KEYRING_SERVICE, &self.account).context("Failed to open keyring entry")
+    }
+
+    /// Whether a keyring backend is reachable, e.g. false on headless Linux without a Secret Service.
+    pub fn available(&self) -> bool {
+        self.entry().is_ok_and(|entry| {
+            matches!(blocking(|| entry.get_password()), Ok(_) | Err(keyring::Error::NoEntry))
+        })
+    }
+}
+
+impl TokenStore for KeyringStore {
+    fn name(&self) -> &'static str {
+        "OS keyring"
+    }
+
+    fn load(&self) -> Result<Option<String>> {
+        let entry = self.entry()?;
+        match blocking(|| entry.get_password()) {
+            Ok(secret) => Ok(Some(secret)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e).context("Failed to read from keyring"),
+        }
+    }
+
+    fn save(&self, secret: &str) -> Result<()> {
+        let entry = self.entry()?;
+        blocking(|| entry.set_password(secret)).context("Failed to save to keyring")
+    }
+
+    fn delete(&self) -> Result<()> {
+        let entry = self.entry()?;
+        match blocking(|| entry.delete_credential()) {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(e).context("Failed to delete from keyring"),
+        }
+    }
+}
+
+/// A secret encrypted with ChaCha20-Poly1305 under a key derived from a passphrase,
+/// for machines without a keyring. The passphrase comes from `IMP_TOKEN_PASSPHRASE`
+/// or a prompt.
+pub struct EncryptedFileStore {
+    pub path: PathBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EncryptedFile {
+    version: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// The derived key is kept for the rest of the process so a load followed by
+/// a save (token refresh) only asks for the passphrase once. Files written in
+/// the same process share a salt, so tokens and passkey keys unlock together.
+static DERIVED_KEY: Mutex<Option<(Vec<u8>, [u8; 32])>> = Mutex::new(None);
+
+impl EncryptedFileStore {
+    /// This file, or else another encrypted secret next to it, e.g. the
+    /// tokens when passkey keys are saved for the first time.
+    fn existing_file(&self) -> Option<PathBuf> {
+        if self.path.exists() {
+            return Some(self.path.clone());
+        }
+        fs::read_dir(self.path.parent()?)
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .find(|path| path.extension().is_some_and(|ext| ext == "enc"))
+    }
+
+    fn key_for(&self, salt: &[u8], confirm_new: bool) -> Result<[u8; 32]> {
+        let mut cached = DERIVED_KEY.lock().unwrap();
+        if let Some((cached_salt, key)) = cached.as_ref()
+            && cached_salt == salt
+        {
+            return Ok(*key);
+        }
+
+        let passphrase = read_passphrase(confirm_new)?;
+        let mut key = [0u8; 32];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            NonZeroU32::new(PBKDF2_ITERATIONS).unwrap(),
+            salt,
+            passphrase.as_bytes(),
+            &mut key,
+        );
+        *cached = Some((salt.to_vec(), key));
+        Ok(key)
+    }
+}
+
+impl TokenStore for EncryptedFileStore {
+    fn name(&self) -> &'static str {
+        "encrypted file"
+    }
+
+    fn exists(&self) -> bool {
+        self.path.exists()
+    }
+
+    fn load(&self) -> Result<Option<String>> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+        let file = read_encrypted(&self.path)?;
+        let salt = STANDARD.decode(&file.salt)?;
+        let nonce = Nonce::try_assume_unique_for_key(&STANDARD.decode(&file.nonce)?)
+            .map_err(|_| anyhow::anyhow!("Corrupt encrypted tokens"))?;
+        let mut data = STANDARD.decode(&file.ciphertext)?;
+
+        let key = self.key_for(&salt, false)?;
+        let cipher = LessSafeKey::new(UnboundKey::new(&CHACHA20_POLY1305, &key).unwrap());
+        let plaintext = match cipher.open_in_place(nonce, Aad::empty(), &mut data) {
+            Ok(plaintext) => plaintext,
+            Err(_) => {
+                // Don't keep a wrong passphrase around for the next attempt
+                *DERIVED_KEY.lock().unwrap() = None;
+                bail!("Wrong passphrase for encrypted tokens");
+            }
+        };
+
+        Ok(Some(String::from_utf8(plaintext.to_vec()).context("Corrupt encrypted tokens")?))
+    }
+
+    fn save(&self, secret: &str) -> Result<()> {
+        let rng = SystemRandom::new();
+        let cached_salt = || DERIVED_KEY.lock().unwrap().as_ref().map(|(salt, _)| salt.clone());
+
+        // Keep the passphrase that's already in use: unlocking an existing file
+        // checks it against the stored data and caches the key for its salt
+        if let Some(existing) = self.existing_file() {
+            let salt = STANDARD.decode(&read_encrypted(&existing)?.salt)?;
+            if cached_salt() != Some(salt) {
+                EncryptedFileStore { path: existing }.load()?;
+            }
+        }
+
+        let (salt, confirm_new) = match cached_salt() {
+            Some(salt) => (salt, false),
+            None => {
+                let mut salt = vec![0u8; SALT_LEN];
+                rng.fill(&mut salt).map_err(|_| anyhow::anyhow!("Failed to generate salt"))?;
+                (salt, true)
+            }
+        };
+        let key = self.key_for(&salt, confirm_new)?;
+
+        let mut nonce = [0u8; NONCE_LEN];
+        rng.fill(&mut nonce).map_err(|_| anyhow::anyhow!("Failed to generate nonce"))?;
+
+        let mut data = secret.as_bytes().to_vec();
+        let cipher = LessSafeKey::new(UnboundKey::new(&CHACHA20_POLY1305, &key).unwrap());
+        cipher
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut data)
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt tokens"))?;
+
+        let file = EncryptedFile {
+            version: 1,
+            salt: STANDARD.encode(&salt),
+            nonce: STANDARD.encode(nonce),
+            ciphertext: STANDARD.encode(&data),
+        };
+        write_private(&self.path, serde_json::to_string_pretty(&file)?.as_bytes())
+    }
+
+    fn delete(&self) -> Result<()> {
+        if self.path.exists() {
+            fs::remove_file(&self.path)?;
+        }
+        Ok(())
+    }
+}
+
+fn read_encrypted(path: &PathBuf) -> Result<EncryptedFile> {
+    serde_json::from_str(&fs::read_to_string(path)?).context("Failed to parse encrypted tokens")
+}
+
+fn read_passphrase(confirm_new: bool) -> Result<String> {
+    if let Ok(passphrase) = std::env::var("IMP_TOKEN_PASSPHRASE")
+        && !passphrase.is_empty()
+    {
+        return Ok(passphrase);
+    }
+    if !std::io::stdin().is_terminal() {
+        bail!("Tokens are encrypted. Set IMP_TOKEN_PASSPHRASE to unlock them");
+    }
+
+    if !confirm_new {
+        return Ok(rpassword::prompt_password("Passphrase for imp tokens: ")?);
+    }
+
+    let passphrase = rpassword::prompt_password("Choose a passphrase to encrypt your imp tokens: ")?;
+    if passphrase.is_empty() {
+        bail!("Passphrase can't be empty");
+    }
+    if rpassword::prompt_password("Repeat passphrase: ")? != passphrase {
+        bail!("Passphrases don't match");
+    }
+    Ok(passphrase)
+}
+
+/// Writes a file only the owner can read, created that way rather than
+/// narrowed afterwards, and renamed into place so it's never half-written.
+fn write_private(path: &PathBuf, contents: &[u8]) -> Result<()> {
+    fs::create_dir_all(path.parent().unwrap())?;
+    let mut tmp = path.clone().into_os_string();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    // A leftover temp file would keep whatever mode it was created with
+    if tmp.exists() {
+        fs::remove_file(&tmp)?;
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&tmp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)?;
+
+    Ok(())
+}
+
+/// Keyring backends may block on D-Bus, which must not happen on an async worker thread.
+fn blocking<T>(f: impl FnOnce() -> T) -> T {
+    match tokio::runtime::Handle::try_current() {
+        Ok(_) => tokio::task::block_in_place(f),
+        Err(_) => f(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[cfg(unix)]
+    #[test]
+    fn private_files_are_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+        let path = temp_dir("private").join("tokens.json");
+        write_private(&path, b"one").unwrap();
+        write_private(&path, b"two").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "two");
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        assert!(!path.with_extension("json.tmp").exists());
+    }
+
+    #[test]
+    fn new_files_keep_the_existing_passphrase() {
+        let dir = temp_dir("encrypted");
+        let tokens = EncryptedFileStore { path: dir.join("tokens.enc") };
+        let keys = EncryptedFileStore { path: dir.join("passkey-keys.enc") };
+        let salt = |store: &EncryptedFileStore| read_encrypted(&store.path).unwrap().salt;
+
+        // Only this test reads the passphrase or touches the cached key
+        unsafe { std::env::set_var("IMP_TOKEN_PASSPHRASE", "right") };
+        tokens.save("tokens").unwrap();
+
+        // A later run with nothing cached unlocks the tokens and reuses their salt
+        *DERIVED_KEY.lock().unwrap() = None;
+        keys.save("keys").unwrap();
+        assert_eq!(salt(&keys), salt(&tokens));
+
+        // The wrong passphrase can't overwrite anything
+        *DERIVED_KEY.lock().unwrap() = None;
+        unsafe { std::env::set_var("IMP_TOKEN_PASSPHRASE", "wrong") };
+        assert!(tokens.save("other").is_err());
+        *DERIVED_KEY.lock().unwrap() = None;
+        unsafe { std::env::set_var("IMP_TOKEN_PASSPHRASE", "right") };
+        assert_eq!(tokens.load().unwrap().as_deref(), Some("tokens"));
+        assert_eq!(keys.load().unwrap().as_deref(), Some("keys"));
+    }
+}
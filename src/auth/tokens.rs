@@ -1,38 +1,129 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use crate::auth::token_store::{EncryptedFileStore, FileStore, KeyringStore, TokenStore};
 use crate::auth::types::Tokens;
-use std::fs;
-use std::path::PathBuf;
+use crate::config;
+use crate::error::ImpError;
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
 
-fn tokens_path() -> PathBuf {
-    dirs::config_dir()
-        .unwrap()
-        .join("imp")
-        .join("tokens.json")
+/// What a store holds. Both go wherever the profile's `token_store` setting says.
+#[derive(Clone, Copy)]
+enum Secret {
+    Tokens,
+    /// Passkey private keys, so `passkeys.json` only has what's safe to read without unlocking.
+    PasskeyKeys,
+}
+
+impl Secret {
+    fn file_stem(self) -> &'static str {
+        match self {
+            Secret::Tokens => "tokens",
+            Secret::PasskeyKeys => "passkey-keys",
+        }
+    }
+
+    fn keyring_account(self, profile: &str) -> String {
+        match self {
+            Secret::Tokens => profile.to_string(),
+            Secret::PasskeyKeys => format!("{}/passkey-keys", profile),
+        }
+    }
+}
+
+fn legacy_store(profile: &str) -> FileStore {
+    FileStore { path: config::profile_dir_for(profile).join("tokens.json") }
+}
+
+/// The `token_store` setting for `profile`, which needn't be the active one.
+fn store_setting(profile: &str) -> String {
+    let active = config::get();
+    if profile == active.profile {
+        return active.token_store.clone();
+    }
+    config::for_profile(profile).map(|config| config.token_store).unwrap_or_else(|_| "auto".to_string())
+}
+
+fn store_for(profile: &str, secret: Secret) -> Box<dyn TokenStore> {
+    let dir = config::profile_dir_for(profile);
+    let encrypted = || Box::new(EncryptedFileStore { path: dir.join(format!("{}.enc", secret.file_stem())) });
+    let keyring = || Box::new(KeyringStore { account: secret.keyring_account(profile) });
+
+    match store_setting(profile).as_str() {
+        "file" => Box::new(FileStore { path: dir.join(format!("{}.json", secret.file_stem())) }),
+        "encrypted" => encrypted(),
+        "keyring" => keyring(),
+        _ => {
+            static KEYRING_AVAILABLE: OnceLock<bool> = OnceLock::new();
+            if *KEYRING_AVAILABLE.get_or_init(|| keyring().available()) {
+                keyring()
+            } else {
+                encrypted()
+            }
+        }
+    }
 }
 
 pub fn save_tokens(tokens: &Tokens) -> Result<()> {
-    let path = tokens_path();
-    fs::create_dir_all(path.parent().unwrap())?;
-    let json = serde_json::to_string(tokens)?;
-    fs::write(&path, json)?;
+    store_for(&config::get().profile, Secret::Tokens).save(&serde_json::to_string(tokens)?)
+}
+
+pub fn load_tokens() -> Result<Tokens> {
+    let profile = &config::get().profile;
+    let store = store_for(profile, Secret::Tokens);
+    if let Some(json) = store.load()? {
+        return Ok(serde_json::from_str(&json)?);
+    }
 
-    #[cfg(unix)]
+    // Tokens saved before token stores existed are plaintext; move them over
+    let legacy = legacy_store(profile);
+    if store.name() != legacy.name()
+        && let Some(json) = legacy.load()?
     {
-        use std::os::unix::fs::PermissionsExt;
-        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+        store.save(&json)?;
+        legacy.delete()?;
+        eprintln!("Moved your tokens into the {}", store.name());
+        return Ok(serde_json::from_str(&json)?);
     }
 
-    Ok(())
+    Err(ImpError::NotLoggedIn.into())
 }
 
-pub fn load_tokens() -> Result<Tokens> {
-    let path = tokens_path();
-    let json = fs::read_to_string(&path)?;
-    Ok(serde_json::from_str(&json)?)
+/// Human-readable name of where the active profile's tokens are kept.
+pub fn store_name() -> &'static str {
+    store_for(&config::get().profile, Secret::Tokens).name()
+}
+
+/// Whether the given profile has stored tokens, without decrypting them.
+pub fn has_tokens(profile: &str) -> bool {
+    store_for(profile, Secret::Tokens).exists() || legacy_store(profile).exists()
 }
 
 pub fn delete_tokens() -> Result<()> {
-    let path = tokens_path();
-    fs::remove_file(&path)?;
+    delete_tokens_for(&config::get().profile)
+}
+
+pub fn delete_tokens_for(profile: &str) -> Result<()> {
+    store_for(profile, Secret::Tokens).delete()?;
+    legacy_store(profile).delete()?;
     Ok(())
 }
+
+/// Base64url PKCS#8 passkey private keys of the active profile, by credential id.
+pub fn load_passkey_keys() -> Result<BTreeMap<String, String>> {
+    match store_for(&config::get().profile, Secret::PasskeyKeys).load()? {
+        Some(json) => serde_json::from_str(&json).context("Failed to parse passkey keys"),
+        None => Ok(BTreeMap::new()),
+    }
+}
+
+pub fn save_passkey_keys(keys: &BTreeMap<String, String>) -> Result<()> {
+    let store = store_for(&config::get().profile, Secret::PasskeyKeys);
+    if keys.is_empty() {
+        return store.delete();
+    }
+    store.save(&serde_json::to_string(keys)?)
+}
+
+pub fn delete_passkey_keys_for(profile: &str) -> Result<()> {
+    store_for(profile, Secret::PasskeyKeys).delete()
+}
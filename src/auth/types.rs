@@ -1,3 +1,4 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -5,5 +6,29 @@ pub struct Tokens {
     pub access_token: String,
     pub id_token: String,
     pub refresh_token: String,
-    // pub device_key: String,
+    /// Cognito device key for this machine, if the pool tracks devices.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_key: Option<String>,
+}
+
+/// A device Cognito remembers for the signed-in user.
+#[derive(Serialize, Clone)]
+pub struct Device {
+    pub device_key: String,
+    pub name: Option<String>,
+    pub last_authenticated: Option<DateTime<Utc>>,
+    pub last_ip: Option<String>,
+    pub current: bool,
+}
+
+/// A passkey registered with Cognito for the signed-in user.
+#[derive(Serialize, Clone)]
+pub struct Passkey {
+    pub credential_id: String,
+    pub name: String,
+    pub rp_id: String,
+    pub attachment: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    /// Whether the private key lives in this machine's software authenticator.
+    pub on_this_machine: bool,
 }
@@ -0,0 +1,163 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use crate::api::types::{OccurrenceItem, Plan};
+use crate::config;
+
+/// How long cached history is shown without asking the server again.
+const FRESH_FOR_SECS: i64 = 5 * 60;
+
+/// A local mirror of the user's history so `imp view` works offline and answers instantly.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Cache {
+    pub summary: Option<String>,
+    pub summary_synced_at: Option<DateTime<Utc>>,
+    /// Oldest first.
+    pub occurrences: Vec<OccurrenceItem>,
+    pub occurrences_synced_at: Option<DateTime<Utc>>,
+    /// The server's position in the history, so the next pull only gets changes.
+    #[serde(default)]
+    pub occurrences_cursor: Option<String>,
+    /// Active and paused plans, for reminders and the prompt.
+    #[serde(default)]
+    pub plans: Vec<Plan>,
+    pub plans_synced_at: Option<DateTime<Utc>>,
+}
+
+impl Cache {
+    pub fn summary_fresh(&self) -> bool {
+        is_fresh(self.summary_synced_at)
+    }
+
+    pub fn occurrences_fresh(&self) -> bool {
+        is_fresh(self.occurrences_synced_at)
+    }
+
+    pub fn plans_fresh(&self) -> bool {
+        is_fresh(self.plans_synced_at)
+    }
+
+    /// Adds fetched occurrences, replacing cached ones with the same key so
+    /// edits show up, and drops the `deleted` ids.
+    pub fn merge_occurrences(&mut self, fetched: Vec<OccurrenceItem>, deleted: &[String]) {
+        let mut index: HashMap<String, usize> =
+            self.occurrences.iter().enumerate().map(|(i, occ)| (occ.key(), i)).collect();
+        for item in fetched {
+            match index.get(&item.key()) {
+                Some(&i) => self.occurrences[i] = item,
+                None => {
+                    index.insert(item.key(), self.occurrences.len());
+                    self.occurrences.push(item);
+                }
+            }
+        }
+
+        let deleted: HashSet<&str> = deleted.iter().map(String::as_str).collect();
+        if !deleted.is_empty() {
+            self.occurrences.retain(|occ| occ.id.as_deref().is_none_or(|id| !deleted.contains(id)));
+        }
+        self.occurrences.sort_by_key(OccurrenceItem::time);
+    }
+}
+
+fn is_fresh(synced_at: Option<DateTime<Utc>>) -> bool {
+    synced_at.is_some_and(|at| Utc::now() - at < Duration::seconds(FRESH_FOR_SECS))
+}
+
+fn cache_path() -> PathBuf {
+    config::profile_dir().join("cache.json")
+}
+
+/// A missing or unreadable cache is just empty; it's rebuilt from the server.
+pub fn load_cache() -> Cache {
+    fs::read_to_string(cache_path())
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_cache(cache: &Cache) -> Result<()> {
+    let path = cache_path();
+    fs::create_dir_all(path.parent().unwrap())?;
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_string(cache)?).context("Failed to write cache")?;
+    fs::rename(&tmp, &path).context("Failed to write cache")?;
+    Ok(())
+}
+
+/// Marks the cache out of date after something new was logged, keeping its data for offline use.
+pub fn invalidate() -> Result<()> {
+    if !cache_path().exists() {
+        return Ok(());
+    }
+    let mut cache = load_cache();
+    cache.summary_synced_at = None;
+    cache.occurrences_synced_at = None;
+    cache.plans_synced_at = None;
+    save_cache(&cache)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn occ(id: Option<&str>, timestamp: &str, text: &str) -> OccurrenceItem {
+        OccurrenceItem { id: id.map(String::from), timestamp: timestamp.into(), text: text.into(), queued: false }
+    }
+
+    fn texts(cache: &Cache) -> Vec<&str> {
+        cache.occurrences.iter().map(|o| o.text.as_str()).collect()
+    }
+
+    #[test]
+    fn merge_adds_backdated_and_skips_duplicates() {
+        let mut cache = Cache::default();
+        cache.merge_occurrences(vec![occ(Some("a"), "2026-10-17T08:00:00Z", "ran 5k")], &[]);
+        cache.merge_occurrences(
+            vec![
+                occ(Some("a"), "2026-10-17T08:00:00Z", "ran 5k"),
+                occ(Some("b"), "2026-10-10T08:00:00Z", "swam 1k"),
+            ],
+            &[],
+        );
+        assert_eq!(texts(&cache), ["swam 1k", "ran 5k"]);
+    }
+
+    #[test]
+    fn merge_replaces_edits_by_id() {
+        let mut cache = Cache::default();
+        cache.merge_occurrences(vec![occ(Some("a"), "2026-10-17T08:00:00Z", "ran 5k")], &[]);
+        cache.merge_occurrences(vec![occ(Some("a"), "2026-10-16T08:00:00Z", "ran 10k")], &[]);
+        assert_eq!(texts(&cache), ["ran 10k"]);
+        assert_eq!(cache.occurrences[0].timestamp, "2026-10-16T08:00:00Z");
+    }
+
+    #[test]
+    fn merge_drops_deleted_ids() {
+        let mut cache = Cache::default();
+        cache.merge_occurrences(
+            vec![
+                occ(Some("a"), "2026-10-17T08:00:00Z", "ran 5k"),
+                occ(Some("b"), "2026-10-17T09:00:00Z", "ran 5k"),
+                occ(None, "2026-10-17T10:00:00Z", "pushups"),
+            ],
+            &[],
+        );
+        cache.merge_occurrences(Vec::new(), &["a".to_string()]);
+        assert_eq!(cache.occurrences.iter().map(|o| o.key()).collect::<Vec<_>>(), ["b", "2026-10-17T10:00:00Z\npushups"]);
+    }
+
+    #[test]
+    fn merge_without_ids_dedupes_by_time_and_text() {
+        let mut cache = Cache::default();
+        cache.merge_occurrences(vec![occ(None, "2026-10-17T08:00:00Z", "ran 5k")], &[]);
+        cache.merge_occurrences(
+            vec![occ(None, "2026-10-17T08:00:00Z", "ran 5k"), occ(None, "2026-10-17T08:00:00Z", "ran 10k")],
+            &[],
+        );
+        assert_eq!(texts(&cache), ["ran 5k", "ran 10k"]);
+    }
+}
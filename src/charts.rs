@@ -0,0 +1,134 @@
+//! Small terminal charts for `imp view stats`. Everything renders to plain
+//! ASCII without colors when `NO_COLOR` is set or stdout isn't a terminal.
+
+use chrono::{Datelike, Days, NaiveDate};
+use colored::Colorize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::IsTerminal;
+
+const SPARKS: [&str; 8] = ["▁", "▂", "▃", "▄", "▅", "▆", "▇", "█"];
+const ASCII_SPARKS: [&str; 8] = ["_", "_", ".", "-", "-", "=", "*", "#"];
+const ASCII_CELLS: [&str; 5] = [".", "-", "+", "*", "#"];
+
+#[derive(Clone, Copy)]
+pub struct Style {
+    pub fancy: bool,
+}
+
+impl Style {
+    /// Unicode and colors only for a terminal that wants them.
+    pub fn detect() -> Self {
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        Style { fancy: !no_color && std::io::stdout().is_terminal() }
+    }
+
+    pub fn heading(&self, text: &str) -> String {
+        if self.fancy { text.bold().to_string() } else { text.to_string() }
+    }
+
+    pub fn dim(&self, text: &str) -> String {
+        if self.fancy { text.dimmed().to_string() } else { text.to_string() }
+    }
+
+    fn cell(&self, level: usize) -> String {
+        if !self.fancy {
+            return ASCII_CELLS[level].to_string();
+        }
+        match level {
+            0 => "·".dimmed().to_string(),
+            1 => "■".green().dimmed().to_string(),
+            2 => "■".green().to_string(),
+            3 => "■".bright_green().to_string(),
+            _ => "■".bright_green().bold().to_string(),
+        }
+    }
+
+    fn bar(&self, width: usize) -> String {
+        if self.fancy { "█".repeat(width).green().to_string() } else { "#".repeat(width) }
+    }
+}
+
+/// Maps a value to 0 (nothing) through `levels - 1` (the maximum).
+fn level(value: u32, max: u32, levels: usize) -> usize {
+    if value == 0 || max == 0 {
+        return 0;
+    }
+    (value as usize * (levels - 1)).div_ceil(max as usize)
+}
+
+/// A GitHub-style grid of daily counts: one column per week ending with the
+/// week of `end`, one row per weekday with Mondays on top.
+pub fn heatmap(counts: &BTreeMap<NaiveDate, u32>, end: NaiveDate, weeks: u32, style: Style) -> String {
+    let end_monday = end - Days::new(u64::from(end.weekday().num_days_from_monday()));
+    let start = end_monday - Days::new(u64::from(weeks.saturating_sub(1)) * 7);
+    let max = counts.range(start..=end).map(|(_, c)| *c).max().unwrap_or(0);
+    let mondays: Vec<NaiveDate> = (0..weeks).map(|w| start + Days::new(u64::from(w) * 7)).collect();
+
+    // Month names over the week they start in
+    let mut header = String::from("    ");
+    let mut last_month = None;
+    for (i, monday) in mondays.iter().enumerate() {
+        let column = 4 + 2 * i;
+        if last_month != Some(monday.month()) && header.len() <= column {
+            header.push_str(&" ".repeat(column - header.len()));
+            header.push_str(&monday.format("%b").to_string());
+        }
+        last_month = Some(monday.month());
+    }
+
+    let mut lines = vec![style.dim(header.trim_end())];
+    for (row, label) in ["Mon", "", "Wed", "", "Fri", "", "Sun"].iter().enumerate() {
+        let mut line = format!("{:<4}", label);
+        for monday in &mondays {
+            let day = *monday + Days::new(row as u64);
+            if day > end {
+                break;
+            }
+            let count = counts.get(&day).copied().unwrap_or(0);
+            line.push_str(&style.cell(level(count, max, 5)));
+            line.push(' ');
+        }
+        lines.push(style.dim(&line[..4]) + line[4..].trim_end());
+    }
+    lines.join("\n")
+}
+
+pub fn sparkline(values: &[u32], style: Style) -> String {
+    let max = values.iter().copied().max().unwrap_or(0);
+    values
+        .iter()
+        .map(|&v| match (level(v, max, SPARKS.len() + 1), style.fancy) {
+            (0, _) => " ",
+            (level, true) => SPARKS[level - 1],
+            (level, false) => ASCII_SPARKS[level - 1],
+        })
+        .collect()
+}
+
+/// Scales `value` to a bar at most `width` wide.
+pub fn bar(value: u32, max: u32, width: usize, style: Style) -> String {
+    let filled = if max == 0 { 0 } else { (value as usize * width).div_ceil(max as usize) };
+    style.bar(filled)
+}
+
+/// Current and longest runs of consecutive days. The current streak still
+/// counts if the last day was yesterday, since today isn't over.
+pub fn streaks(days: &BTreeSet<NaiveDate>, today: NaiveDate) -> (u32, u32) {
+    let mut longest = 0;
+    let mut run = 0;
+    let mut previous: Option<NaiveDate> = None;
+    for &day in days {
+        run = match previous {
+            Some(p) if p.succ_opt() == Some(day) => run + 1,
+            _ => 1,
+        };
+        longest = longest.max(run);
+        previous = Some(day);
+    }
+
+    let current = match previous {
+        Some(last) if last == today || last.succ_opt() == Some(today) => run,
+        _ => 0,
+    };
+    (current, longest)
+}
@@ -0,0 +1,546 @@
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Datelike, Days, Local, Months, NaiveDate, Timelike, Utc, Weekday};
+use colored::Colorize;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use crate::api::client::ApiClient;
+use crate::api::types::{Plan, PlanStatus};
+use crate::cache::{self, Cache};
+use crate::charts::{self, Style};
+use crate::commands::sync;
+use crate::error::{self, ImpError};
+use crate::output::{self, OutputFormat};
+use crate::parse::cadence::{Cadence, Recurrence, plan_activity, plan_cadence};
+use crate::parse::log::parse_log;
+use crate::{agenda, queue};
+
+/// Activities need this many logs in the period before they get insights.
+const MIN_LOGS: usize = 3;
+const TOP_ACTIVITIES: usize = 8;
+/// Change between the two halves of the period that counts as a trend.
+const TREND_THRESHOLD: f64 = 0.15;
+/// Plans below this adherence get a suggestion.
+const LOW_ADHERENCE: f64 = 0.6;
+
+#[derive(Serialize)]
+struct Analysis {
+    since: NaiveDate,
+    until: NaiveDate,
+    plans: Vec<PlanInsight>,
+    activities: Vec<ActivityInsight>,
+    suggestions: Vec<Insight>,
+}
+
+#[derive(Serialize)]
+struct PlanInsight {
+    goal: String,
+    schedule: String,
+    done: u32,
+    expected: u32,
+    adherence: f64,
+    /// Scheduled days of the week that were skipped most, worst first.
+    most_missed: Vec<Weekday>,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+enum Trend {
+    Up,
+    Down,
+    Flat,
+    New,
+}
+
+#[derive(Serialize)]
+struct ActivityInsight {
+    activity: String,
+    logs: usize,
+    amount: u32,
+    trend: Trend,
+    /// Change from the first to the second half of the period.
+    change: Option<f64>,
+    /// Median hour of day it's logged at, local time.
+    usual_hour: u32,
+    streak: u32,
+    /// The streak ends tonight unless something is logged today.
+    at_risk: bool,
+}
+
+#[derive(Serialize)]
+struct Insight {
+    /// 1 is most urgent.
+    priority: u8,
+    text: String,
+    source: &'static str,
+}
+
+/// An occurrence reduced to what the analysis needs.
+struct Entry {
+    time: DateTime<Local>,
+    activity: String,
+    text: String,
+    amount: u32,
+}
+
+pub async fn analyze_command(period: &str, refresh: bool) -> Result<()> {
+    let days = period_days(period)?;
+    let today = Local::now().date_naive();
+    let since = today.checked_sub_days(Days::new(days - 1)).context("Period is too long")?;
+
+    let mut cache = cache::load_cache();
+    let api = match ApiClient::authenticated().await {
+        Ok(api) => Some(api),
+        Err(e) if matches!(error::find(&e), Some(ImpError::NetworkUnavailable)) => None,
+        Err(e) => return Err(e),
+    };
+
+    // Plans and server suggestions need the network; everything else works from the cache
+    let mut plans = Vec::new();
+    let mut server = Vec::new();
+    match &api {
+        Some(api) => {
+            if refresh || !cache.occurrences_fresh() {
+                update(api, &mut cache, refresh).await?;
+            }
+            match api.plans(false).await {
+                Ok(response) => plans = response.plans,
+                Err(e) if e.is_transient() => offline_note(),
+                Err(e) => return Err(ImpError::from(e)).context("Failed to get plans"),
+            }
+            let start = since.and_hms_opt(0, 0, 0).and_then(|t| t.and_local_timezone(Local).earliest());
+            if let Some(start) = start
+                && let Ok(response) = api.suggestions(start.with_timezone(&Utc)).await
+            {
+                server = response.suggestions;
+            }
+        }
+        None if cache.occurrences.is_empty() => bail!(ImpError::NetworkUnavailable),
+        None => offline_note(),
+    }
+
+    let entries = entries(&cache)?;
+    let plan_insights: Vec<PlanInsight> = plans
+        .iter()
+        .filter(|plan| plan.status != PlanStatus::Archived)
+        .filter_map(|plan| plan_insight(plan, &entries, since, today))
+        .collect();
+    let activities = activity_insights(&entries, since, today);
+
+    let mut suggestions = suggest(&plan_insights, &activities, &entries, since, today);
+    suggestions.extend(server.into_iter().map(|s| Insight {
+        priority: s.priority.unwrap_or(3),
+        text: s.text,
+        source: "server",
+    }));
+    // Local ones first on a tie; the server may say the same thing in other words
+    suggestions.sort_by_key(|s| (s.priority, s.source == "server"));
+    let mut seen = BTreeSet::new();
+    suggestions.retain(|s| seen.insert(s.text.to_lowercase()));
+
+    let analysis = Analysis { since, until: today, plans: plan_insights, activities, suggestions };
+    match output::get() {
+        OutputFormat::Json => output::print_record(&analysis),
+        OutputFormat::Csv => output::print_records(&analysis.suggestions),
+        OutputFormat::Text => {
+            print_analysis(&analysis, Style::detect());
+            Ok(())
+        }
+    }
+}
+
+async fn update(api: &ApiClient, cache: &mut Cache, full: bool) -> Result<()> {
+    match sync::pull_occurrences(api, cache, full).await {
+        Ok(()) => cache::save_cache(cache),
+        Err(e) if !cache.occurrences.is_empty() && matches!(error::find(&e), Some(ImpError::NetworkUnavailable)) => {
+            offline_note();
+            Ok(())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn offline_note() {
+    eprintln!("{}", "Offline, analyzing cached history without plans".dimmed());
+}
+
+/// "4w", "30d", "3 months", "year"
+fn period_days(input: &str) -> Result<u64> {
+    let input = input.trim().to_lowercase().replace(' ', "");
+    let digits = input.find(|c: char| !c.is_ascii_digit()).unwrap_or(input.len());
+    let (count, unit) = input.split_at(digits);
+    let count: u64 = if count.is_empty() { 1 } else { count.parse().context("Period is too long")? };
+
+    let today = Local::now().date_naive();
+    let days = match unit.trim_end_matches('s') {
+        "d" | "day" => Some(count),
+        "w" | "wk" | "week" => count.checked_mul(7),
+        "m" | "mo" | "month" => u32::try_from(count)
+            .ok()
+            .and_then(|count| today.checked_sub_months(Months::new(count)))
+            .map(|start| (today - start).num_days() as u64),
+        "quarter" => count.checked_mul(91),
+        "y" | "yr" | "year" => count.checked_mul(365),
+        _ => bail!("Can't read period \"{}\", use e.g. 2w, 30d, 3 months or year", input),
+    }
+    .context("Period is too long")?;
+    if days == 0 {
+        bail!("The period must be at least a day");
+    }
+    Ok(days)
+}
+
+/// Cached and queued occurrences, oldest first, without plan definitions.
+fn entries(cache: &Cache) -> Result<Vec<Entry>> {
+    let server = cache
+        .occurrences
+        .iter()
+        .filter_map(|occ| Some((occ.time()?, occ.text.clone())));
+    let queued = queue::load_queue()?
+        .into_iter()
+        .map(|entry| (entry.occurred_at.unwrap_or(entry.captured_at), entry.raw_input));
+
+    let mut entries: Vec<Entry> = server
+        .chain(queued)
+        .filter(|(_, text)| !text.contains("#plan"))
+        .map(|(time, text)| {
+            let parsed = parse_log(&text);
+            Entry {
+                time: time.with_timezone(&Local),
+                activity: parsed
+                    .as_ref()
+                    .and_then(|p| p.activity.clone())
+                    .unwrap_or_else(|| text.to_lowercase()),
+                amount: parsed
+                    .and_then(|p| p.count.map(|count| count.saturating_mul(p.sets.unwrap_or(1))))
+                    .unwrap_or(1),
+                text,
+            }
+        })
+        .collect();
+    entries.sort_by_key(|entry| entry.time);
+    Ok(entries)
+}
+
+/// How well a plan was kept in the period, judged by its locally parsed schedule.
+fn plan_insight(plan: &Plan, entries: &[Entry], since: NaiveDate, today: NaiveDate) -> Option<PlanInsight> {
+    let cadence = plan_cadence(&plan.goal, plan.cadence.as_deref())?;
+    let activity = plan_activity(&plan.goal)?;
+    let done_days: BTreeSet<NaiveDate> = entries
+        .iter()
+        .filter(|e| agenda::counts_towards(&activity, &e.activity, &e.text))
+        .map(|e| e.time.date_naive())
+        .collect();
+
+    let start = since.max(plan.created_at.with_timezone(&Local).date_naive());
+    let end = cadence.until.map_or(today, |until| until.min(today));
+    // Today only counts once it's done, since it isn't over yet
+    let window: Vec<NaiveDate> = start
+        .iter_days()
+        .take_while(|day| *day <= end)
+        .filter(|day| !plan.paused_on(*day) && (*day < today || done_days.contains(day)))
+        .collect();
+    if window.is_empty() {
+        return None;
+    }
+
+    let (done, expected, scheduled, missed) = adherence(&cadence, &window, &done_days);
+    // Days missed more often than the plan as a whole, worst first
+    let overall = f64::from(expected - done) / f64::from(expected.max(1));
+    let mut most_missed: Vec<(Weekday, f64)> = week()
+        .zip(scheduled.into_iter().zip(missed))
+        .filter(|(_, (scheduled, _))| *scheduled > 0)
+        .map(|(day, (scheduled, missed))| (day, f64::from(missed) / f64::from(scheduled)))
+        .filter(|(_, rate)| *rate > overall)
+        .collect();
+    most_missed.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    Some(PlanInsight {
+        goal: plan.goal.clone(),
+        schedule: cadence.recurrence.to_string(),
+        done,
+        expected,
+        adherence: if expected == 0 { 1.0 } else { f64::from(done) / f64::from(expected) },
+        most_missed: most_missed.into_iter().map(|(day, _)| day).collect(),
+    })
+}
+
+/// Sessions done and expected over `window`, and sessions scheduled and missed
+/// per day of the week from Monday, for schedules tied to days.
+fn adherence(
+    cadence: &Cadence,
+    window: &[NaiveDate],
+    done_days: &BTreeSet<NaiveDate>,
+) -> (u32, u32, [u32; 7], [u32; 7]) {
+    let mut scheduled_on = [0; 7];
+    let mut missed = [0; 7];
+    let scheduled: Vec<NaiveDate> = match &cadence.recurrence {
+        Recurrence::Daily => window.to_vec(),
+        Recurrence::OnDays { days } => window.iter().copied().filter(|d| days.contains(&d.weekday())).collect(),
+        Recurrence::EveryNDays { days } => {
+            let expected = (window.len() as u32).div_ceil(*days);
+            let done = window.iter().filter(|d| done_days.contains(d)).count() as u32;
+            return (done.min(expected), expected, scheduled_on, missed);
+        }
+        Recurrence::PerWeek { times } => {
+            let mut weeks: BTreeMap<(i32, u32), (u32, u32)> = BTreeMap::new();
+            for day in window {
+                let week = weeks.entry((day.iso_week().year(), day.iso_week().week())).or_default();
+                week.0 += 1;
+                week.1 += u32::from(done_days.contains(day));
+            }
+            // Partial weeks at the edges expect a share of the sessions
+            let (done, expected) = weeks.values().fold((0, 0), |(done, expected), (len, count)| {
+                let due = (times * len).div_ceil(7);
+                (done + count.min(&due), expected + due)
+            });
+            return (done, expected, scheduled_on, missed);
+        }
+    };
+
+    let mut done = 0;
+    for day in &scheduled {
+        let weekday = day.weekday().num_days_from_monday() as usize;
+        scheduled_on[weekday] += 1;
+        if done_days.contains(day) {
+            done += 1;
+        } else {
+            missed[weekday] += 1;
+        }
+    }
+    (done, scheduled.len() as u32, scheduled_on, missed)
+}
+
+fn activity_insights(entries: &[Entry], since: NaiveDate, today: NaiveDate) -> Vec<ActivityInsight> {
+    let mut by_activity: HashMap<&str, Vec<&Entry>> = HashMap::new();
+    for entry in entries {
+        by_activity.entry(&entry.activity).or_default().push(entry);
+    }
+    let middle = since + Days::new(((today - since).num_days() as u64).div_ceil(2));
+
+    let mut insights: Vec<ActivityInsight> = by_activity
+        .into_iter()
+        .filter_map(|(activity, all)| {
+            let recent: Vec<&&Entry> = all.iter().filter(|e| e.time.date_naive() >= since).collect();
+            if recent.len() < MIN_LOGS {
+                return None;
+            }
+
+            let first = recent
+                .iter()
+                .filter(|e| e.time.date_naive() < middle)
+                .map(|e| e.amount)
+                .fold(0, u32::saturating_add);
+            let second = recent
+                .iter()
+                .filter(|e| e.time.date_naive() >= middle)
+                .map(|e| e.amount)
+                .fold(0, u32::saturating_add);
+            let change = (first > 0).then(|| (f64::from(second) - f64::from(first)) / f64::from(first));
+            let trend = match change {
+                None => Trend::New,
+                Some(c) if c > TREND_THRESHOLD => Trend::Up,
+                Some(c) if c < -TREND_THRESHOLD => Trend::Down,
+                Some(_) => Trend::Flat,
+            };
+
+            let mut hours: Vec<u32> = recent.iter().map(|e| e.time.hour()).collect();
+            hours.sort_unstable();
+
+            let days: BTreeSet<NaiveDate> = all.iter().map(|e| e.time.date_naive()).collect();
+            let (streak, _) = charts::streaks(&days, today);
+
+            Some(ActivityInsight {
+                activity: activity.to_string(),
+                logs: recent.len(),
+                amount: first + second,
+                trend,
+                change,
+                usual_hour: hours[hours.len() / 2],
+                streak,
+                at_risk: streak >= 2 && !days.contains(&today),
+            })
+        })
+        .collect();
+    insights.sort_by(|a, b| b.logs.cmp(&a.logs).then_with(|| a.activity.cmp(&b.activity)));
+    insights.truncate(TOP_ACTIVITIES);
+    insights
+}
+
+/// Concrete next steps, most urgent first.
+fn suggest(
+    plans: &[PlanInsight],
+    activities: &[ActivityInsight],
+    entries: &[Entry],
+    since: NaiveDate,
+    today: NaiveDate,
+) -> Vec<Insight> {
+    let mut suggestions = Vec::new();
+    let mut add = |priority: u8, text: String| suggestions.push(Insight { priority, text, source: "local" });
+
+    for activity in activities.iter().filter(|a| a.at_risk) {
+        add(1, format!(
+            "Log {} today to keep your {}-day streak going; you usually do it around {}",
+            activity.activity,
+            activity.streak,
+            hour_label(activity.usual_hour),
+        ));
+    }
+
+    for plan in plans.iter().filter(|p| p.adherence < LOW_ADHERENCE) {
+        let text = match plan.most_missed.first() {
+            Some(day) => format!(
+                "\"{}\" is at {:.0}% ({} of {}); {}s are missed most, plan a fixed time for them or move that session",
+                plan.goal,
+                plan.adherence * 100.0,
+                plan.done,
+                plan.expected,
+                day_name(*day),
+            ),
+            None if plan.done == 0 => format!(
+                "\"{}\" hasn't been logged in this period; restart it with a lighter schedule than {}",
+                plan.goal,
+                plan.schedule,
+            ),
+            None => format!(
+                "\"{}\" is at {:.0}% ({} of {}); consider a lighter schedule than {}",
+                plan.goal,
+                plan.adherence * 100.0,
+                plan.done,
+                plan.expected,
+                plan.schedule,
+            ),
+        };
+        add(2, text);
+    }
+
+    for activity in activities.iter().filter(|a| a.trend == Trend::Down) {
+        add(3, format!(
+            "{} is down {:.0}% in the second half of the period; schedule it around {}, when you usually manage it",
+            capitalize(&activity.activity),
+            -activity.change.unwrap_or_default() * 100.0,
+            hour_label(activity.usual_hour),
+        ));
+    }
+
+    // Without plans to compare against, the quietest day of the week stands out
+    if plans.is_empty() {
+        let mut per_day: [BTreeSet<NaiveDate>; 7] = Default::default();
+        for entry in entries.iter().filter(|e| e.time.date_naive() >= since) {
+            per_day[entry.time.weekday().num_days_from_monday() as usize].insert(entry.time.date_naive());
+        }
+        let active_days: usize = per_day.iter().map(BTreeSet::len).sum();
+        if (today - since).num_days() >= 13 && active_days >= 7 {
+            let quietest = week().min_by_key(|day| per_day[day.num_days_from_monday() as usize].len());
+            if let Some(day) = quietest {
+                add(4, format!("{}s are your least active day; a short session then would even out your week", day_name(day)));
+            }
+        }
+    }
+
+    for activity in activities.iter().filter(|a| a.trend == Trend::Up) {
+        add(5, format!(
+            "{} is up {:.0}%, keep it going",
+            capitalize(&activity.activity),
+            activity.change.unwrap_or_default() * 100.0,
+        ));
+    }
+
+    suggestions
+}
+
+fn print_analysis(analysis: &Analysis, style: Style) {
+    println!(
+        "{}",
+        style.heading(&format!("Analysis, {} to {}", analysis.since.format("%a %Y-%m-%d"), analysis.until.format("%a %Y-%m-%d")))
+    );
+
+    if !analysis.plans.is_empty() {
+        println!("\n{}", style.heading("Plans"));
+        let width = analysis.plans.iter().map(|p| p.goal.chars().count()).max().unwrap_or(0).min(40);
+        for plan in &analysis.plans {
+            let missed = match plan.most_missed.first() {
+                Some(day) => style.dim(&format!("  most missed: {}", day)),
+                None => String::new(),
+            };
+            let line = format!(
+                "  {:<width$}  {:>3}/{:<3} {:>4.0}%  {}{}",
+                plan.goal,
+                plan.done,
+                plan.expected,
+                plan.adherence * 100.0,
+                charts::bar(plan.done, plan.expected.max(plan.done), 10, style),
+                missed,
+            );
+            println!("{}", line.trim_end());
+        }
+    }
+
+    if !analysis.activities.is_empty() {
+        println!("\n{}", style.heading("Activities"));
+        let width = analysis.activities.iter().map(|a| a.activity.chars().count()).max().unwrap_or(0).max(8);
+        for activity in &analysis.activities {
+            let trend = match (activity.trend, activity.change) {
+                (Trend::Up, Some(c)) => format!("up {:.0}%", c * 100.0),
+                (Trend::Down, Some(c)) => format!("down {:.0}%", -c * 100.0),
+                (Trend::New, _) => "new".to_string(),
+                _ => "steady".to_string(),
+            };
+            let streak = match (activity.streak, activity.at_risk) {
+                (0, _) => String::new(),
+                (n, true) => format!("{}-day streak, log today", n),
+                (n, false) => format!("{}-day streak", n),
+            };
+            println!(
+                "  {:<width$}  {:>4} logs  {:<9}  usually {:<17}  {}",
+                activity.activity,
+                activity.logs,
+                trend,
+                hour_label(activity.usual_hour),
+                streak,
+            );
+        }
+    }
+
+    println!("\n{}", style.heading("Suggestions"));
+    if analysis.suggestions.is_empty() {
+        println!("  {}", style.dim("Nothing stands out. Log more for sharper suggestions"));
+    }
+    for (i, suggestion) in analysis.suggestions.iter().enumerate() {
+        println!("  {}. {}", i + 1, suggestion.text);
+    }
+}
+
+fn week() -> impl Iterator<Item = Weekday> {
+    std::iter::successors(Some(Weekday::Mon), |day| Some(day.succ())).take(7)
+}
+
+/// 7 -> "07:00 (morning)"
+fn hour_label(hour: u32) -> String {
+    let part = match hour {
+        5..=11 => "morning",
+        12..=16 => "afternoon",
+        17..=21 => "evening",
+        _ => "night",
+    };
+    format!("{:02}:00 ({})", hour, part)
+}
+
+fn day_name(day: Weekday) -> &'static str {
+    match day {
+        Weekday::Mon => "Monday",
+        Weekday::Tue => "Tuesday",
+        Weekday::Wed => "Wednesday",
+        Weekday::Thu => "Thursday",
+        Weekday::Fri => "Friday",
+        Weekday::Sat => "Saturday",
+        Weekday::Sun => "Sunday",
+    }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
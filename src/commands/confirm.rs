@@ -1,47 +1,25 @@
-use anyhow::{Result, bail};
+use anyhow::{Context, Result};
+use chrono::Local;
 use colored::Colorize;
-use serde::{Deserialize, Serialize};
-use uuid::Uuid;
-use chrono::{DateTime, Utc};
 use std::io::{self, Write};
-use crate::auth::token_manager;
-
-#[derive(Deserialize)]
-struct ConfirmationResponse {
-    id: Uuid,
-    #[allow(dead_code)]
-    user_id: Uuid,
-    created_at: DateTime<Utc>,
-    #[allow(dead_code)]
-    log_id: Uuid,
-    prompt_shown: String,
-    choices: Vec<String>,
-}
-
-#[derive(Serialize)]
-struct ConfirmRequest {
-    confirmation_id: Uuid,
-    raw_input: String,
-}
+use crate::api::client::ApiClient;
+use crate::error::ImpError;
+use crate::api::types::ConfirmRequest;
+use crate::output;
 
 pub async fn confirm_command() -> Result<()> {
-    let token = token_manager::get_valid_token().await?;
+    let api = ApiClient::authenticated().await?;
 
-    let client = reqwest::Client::new();
-    let response = client
-        .get("https://api.iepok.com/confirm")
-        .bearer_auth(&token)
-        .send()
-        .await?;
+    let confirmations = api.confirmations()
+        .await
+        .map_err(ImpError::from)
+        .context("Failed to get confirmations")?;
 
-    if !response.status().is_success() {
-        let status = response.status();
-        let body = response.text().await?;
-        bail!("Failed to get confirmations: {} - {}", status, body);
+    // Scripts get the pending list; answering them stays interactive
+    if output::is_machine() {
+        return output::print_records(&confirmations);
     }
 
-    let confirmations: Vec<ConfirmationResponse> = response.json().await?;
-
     if confirmations.is_empty() {
         println!("{}", "No pending confirmations".dimmed());
         return Ok(());
@@ -51,13 +29,13 @@ pub async fn confirm_command() -> Result<()> {
 
     for confirmation in confirmations {
         println!("{}", "─".repeat(50).dimmed());
-        println!("{} {}", "Created:".dimmed(), confirmation.created_at.format("%Y-%m-%d %H:%M"));
+        println!("{} {}", "Created:".dimmed(), confirmation.created_at.with_timezone(&Local).format("%Y-%m-%d %H:%M"));
         println!("\n{}\n", confirmation.prompt_shown.bright_white());
 
         for (i, choice) in confirmation.choices.iter().enumerate() {
             println!("  {}. {}", (i + 1).to_string().bright_cyan(), choice);
         }
-        println!("  {}. {}", "0".bright_cyan(), "Enter custom response");
+        println!("  {}. Enter custom response", "0".bright_cyan());
 
         print!("\n{} ", "Your choice:".bright_green());
         io::stdout().flush()?;
@@ -88,22 +66,14 @@ pub async fn confirm_command() -> Result<()> {
             continue;
         }
 
-        let confirm_response = client
-            .post("https://api.iepok.com/confirm")
-            .bearer_auth(&token)
-            .json(&ConfirmRequest {
-                confirmation_id: confirmation.id,
-                raw_input: raw_input.clone(),
-            })
-            .send()
-            .await?;
+        let request = ConfirmRequest {
+            confirmation_id: confirmation.id,
+            raw_input,
+        };
 
-        if confirm_response.status().is_success() {
-            println!("{} {}\n", "✓".bright_green(), "Confirmed".bright_green());
-        } else {
-            let status = confirm_response.status();
-            let body = confirm_response.text().await?;
-            println!("{} {} - {}\n", "✗".red(), status, body);
+        match api.confirm(&request).await {
+            Ok(()) => println!("{} {}\n", "✓".bright_green(), "Confirmed".bright_green()),
+            Err(e) => println!("{} {}\n", "✗".red(), e),
         }
     }
 
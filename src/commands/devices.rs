@@ -1,5 +1,110 @@
-use anyhow::Result;
+use anyhow::{Result, bail};
+use chrono::Local;
+use colored::Colorize;
+use crate::auth::{auth, token_manager, tokens};
+use crate::output;
+
+/// Name this machine is registered under in the device list.
+pub fn device_name() -> String {
+    std::env::var("COMPUTERNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .or_else(|| {
+            std::process::Command::new("hostname")
+                .output()
+                .ok()
+                .and_then(|o| String::from_utf8(o.stdout).ok())
+        })
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .map(|name| format!("imp on {}", name))
+        .unwrap_or_else(|| "imp".to_string())
+}
+
+pub async fn devices_command() -> Result<()> {
+    let access_token = token_manager::get_valid_token().await?;
+    let current_key = tokens::load_tokens()?.device_key;
+
+    let devices = auth::list_devices(&access_token, current_key.as_deref()).await?;
+
+    if output::is_machine() {
+        return output::print_records(&devices);
+    }
+
+    if devices.is_empty() {
+        println!("{}", "No remembered devices".dimmed());
+        return Ok(());
+    }
+
+    println!(
+        "  {:<24} {:<48} {:<17} {}",
+        "NAME".dimmed(), "DEVICE KEY".dimmed(), "LAST SIGN-IN".dimmed(), "LAST IP".dimmed()
+    );
+    for device in devices {
+        let marker = if device.current { "*".bright_green() } else { " ".normal() };
+        let last_seen = device
+            .last_authenticated
+            .map(|t| t.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|| "-".to_string());
+        println!(
+            "{} {:<24} {:<48} {:<17} {}",
+            marker,
+            device.name.as_deref().unwrap_or("(unnamed)"),
+            device.device_key,
+            last_seen,
+            device.last_ip.as_deref().unwrap_or("-"),
+        );
+    }
+
+    Ok(())
+}
+
+/// Cognito can't revoke another device's refresh token, so forgetting a device
+/// only stops it from skipping sign-in checks. Ending its sessions takes a
+/// global sign-out, which is offered after the device is forgotten.
+pub async fn remove_device_command(device_id: &str, force: bool, logout_all: bool, yes: bool) -> Result<()> {
+    let access_token = token_manager::get_valid_token().await?;
+    let tokens = tokens::load_tokens()?;
+
+    let devices = auth::list_devices(&access_token, tokens.device_key.as_deref()).await?;
+    let Some(device) = devices.into_iter().find(|d| d.device_key == device_id) else {
+        bail!("Device {} not found. Run: imp devices", device_id);
+    };
+
+    if device.current && !force {
+        bail!("That's this device. Use `imp logout`, or pass --force to forget it anyway");
+    }
+
+    let name = device.name.as_deref().unwrap_or("(unnamed)");
+    if !yes && !output::confirm(format!("Forget {} ({})?", name.bright_white(), device.device_key.dimmed()))? {
+        output::progress("Cancelled".dimmed());
+        return Ok(());
+    }
+
+    auth::forget_device(&access_token, &device.device_key).await?;
+
+    if device.current {
+        // Our refresh token is tied to the forgotten device, so sign out here too
+        auth::logout(&tokens.refresh_token).await.ok();
+        tokens::delete_tokens().ok();
+        output::say(format!("{} Forgot {} and signed out of this device", "✓".bright_green(), name));
+        return Ok(());
+    }
+
+    let logout_all = logout_all
+        || (!yes
+            && output::confirm(format!(
+                "{} may still be signed in. Sign out everywhere to end its sessions? You'll need to log in again here too",
+                name
+            ))?);
+    if logout_all {
+        auth::global_logout(&access_token).await?;
+        tokens::delete_tokens().ok();
+        output::say(format!("{} Forgot {} and signed out everywhere", "✓".bright_green(), name));
+    } else {
+        output::say(format!("{} Forgot {}", "✓".bright_green(), name));
+        output::say("It stays signed in until its session expires. To end it now, run: imp logout --all");
+    }
 
-pub fn devices_command() -> Result<()> {
     Ok(())
 }
@@ -1,34 +1,158 @@
 use colored::Colorize;
-use anyhow::{Result, bail};
-use crate::auth::token_manager;
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Local, Utc};
+use serde::Serialize;
+use std::io;
+use uuid::Uuid;
+use crate::api::client::ApiClient;
+use crate::api::types::LogRequest;
+use crate::auth::tokens;
+use crate::cache;
+use crate::commands::sync;
+use crate::config;
+use crate::error::ImpError;
+use crate::output;
+use crate::parse::log::parse_log;
+use crate::queue::{self, QueuedLog};
+use crate::time;
 
-pub async fn log_command(args: &[String]) -> Result<()> {
-    if !args[0].contains(' ') {
-        bail!("Invalid log command");
+/// What `--output json|csv` prints for each log.
+#[derive(Serialize)]
+struct LoggedRecord {
+    /// Set once the server has it; queued logs don't have one yet.
+    id: Option<String>,
+    raw_input: String,
+    occurred_at: Option<DateTime<Utc>>,
+    queued: bool,
+}
+
+impl LoggedRecord {
+    fn new(entry: &QueuedLog, id: Option<String>, queued: bool) -> Self {
+        LoggedRecord { id, raw_input: entry.raw_input.clone(), occurred_at: entry.occurred_at, queued }
     }
+}
+
+pub async fn log_command(
+    words: Vec<String>,
+    at: Option<String>,
+    yesterday: bool,
+    tags: Vec<String>,
+    note: Option<String>,
+) -> Result<()> {
+    let at = match (yesterday, at) {
+        (true, Some(at)) => Some(format!("yesterday {}", at)),
+        (true, None) => Some("yesterday".to_string()),
+        (false, at) => at,
+    };
+    let occurred_at = at.as_deref().map(time::parse_at).transpose()?;
+
+    // `imp log -` takes one log per line, e.g. piped from a file
+    let lines = if words == ["-"] {
+        io::stdin().lines().collect::<Result<Vec<_>, _>>().context("Failed to read stdin")?
+    } else {
+        vec![words.join(" ")]
+    };
 
-    let token = token_manager::get_valid_token().await?;
+    let captured_at = Utc::now();
+    let entries: Vec<QueuedLog> = lines
+        .iter()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| QueuedLog {
+            key: Uuid::new_v4(),
+            raw_input: with_tags(line, &tags),
+            captured_at,
+            occurred_at,
+            note: note.clone(),
+        })
+        .collect();
 
-    println!("{} {}", "Logging:".bright_green().bold(), args[0].cyan());
+    if entries.is_empty() {
+        bail!("Nothing to log");
+    }
+
+    let when = occurred_at
+        .map(|at| format!(" at {}", at.with_timezone(&Local).format("%a %Y-%m-%d %H:%M")).dimmed().to_string())
+        .unwrap_or_default();
+    if !output::is_machine() {
+        for entry in &entries {
+            println!("{} {}{}", "Logging:".bright_green().bold(), entry.raw_input.cyan(), when);
+            if let Some(parsed) = parse_log(&entry.raw_input) {
+                println!("         {}", parsed.to_string().dimmed());
+            }
+        }
+    }
 
-    let client = reqwest::Client::new();
-    let response = client
-        .post("https://api.iepok.com/log")
-        .bearer_auth(token)
-        .json(&serde_json::json!({
-            "raw_input": args[0]
-        }))
-        .send()
-        .await?;
+    let mut logged = Vec::new();
+    let api = match ApiClient::authenticated().await {
+        Ok(api) => api,
+        // Signed in but the token can't be refreshed right now, keep the logs for later
+        Err(_) if tokens::has_tokens(&config::get().profile) => return queue_logs(logged, entries),
+        Err(e) => return Err(e),
+    };
 
-    if !response.status().is_success() {
-        let status = response.status();
-        let body = response.text().await?;
-        bail!("Failed to log: {} - {}", status, body);
+    // Older queued logs go first so the server sees them in order
+    if sync::replay_queue(&api).await?.remaining > 0 {
+        return queue_logs(logged, entries);
     }
 
-    println!("{}", "✓ Logged".bright_green());
+    let mut pending = entries.into_iter();
+    while let Some(entry) = pending.next() {
+        let request = LogRequest {
+            raw_input: entry.raw_input.clone(),
+            occurred_at: entry.occurred_at,
+            note: entry.note.clone(),
+            parsed: parse_log(&entry.raw_input),
+            cadence: None,
+        };
+        match api.log(&request, Some(entry.key)).await {
+            Ok(id) => {
+                cache::invalidate()?;
+                if !output::is_machine() {
+                    let shown = id.as_deref().map(|id| format!(" {}", id.dimmed())).unwrap_or_default();
+                    println!("{}{}", "✓ Logged".bright_green(), shown);
+                }
+                logged.push(LoggedRecord::new(&entry, id, false));
+            }
+            Err(e) if e.is_transient() => {
+                return queue_logs(logged, std::iter::once(entry).chain(pending).collect());
+            }
+            Err(e) => return Err(ImpError::from(e)).context("Failed to log"),
+        }
+    }
 
+    print_logged(&logged)
+}
+
+/// Tags go to the server as hashtags, the same way plans are marked with `#plan`.
+fn with_tags(text: &str, tags: &[String]) -> String {
+    tags.iter()
+        .map(|tag| tag.trim_start_matches('#'))
+        .filter(|tag| !tag.is_empty())
+        .fold(text.to_string(), |text, tag| format!("{} #{}", text, tag))
+}
+
+/// Queues `entries`; `logged` are the ones that made it to the server before that.
+fn queue_logs(mut logged: Vec<LoggedRecord>, entries: Vec<QueuedLog>) -> Result<()> {
+    let count = entries.len();
+    for entry in entries {
+        logged.push(LoggedRecord::new(&entry, None, true));
+        queue::enqueue(entry)?;
+    }
+    if output::is_machine() {
+        return print_logged(&logged);
+    }
+    if count == 1 {
+        println!("{}", "⏳ Offline, queued. It will be sent on the next sync.".yellow());
+    } else {
+        println!("{}", format!("⏳ Offline, queued {} logs. They will be sent on the next sync.", count).yellow());
+    }
     Ok(())
 }
 
+fn print_logged(logged: &[LoggedRecord]) -> Result<()> {
+    if output::is_machine() {
+        output::print_records(logged)?;
+    }
+    Ok(())
+}
@@ -1,40 +1,105 @@
-use crate::auth::{auth, auth::OtpResult, tokens, token_manager};
-use anyhow::Result;
-use std::io::{self, Write};
+use crate::auth::{auth, auth::OtpResult, authenticator, browser, tokens, token_manager};
+use crate::auth::types::Tokens;
+use crate::commands::devices::device_name;
+use crate::{config, output};
+use anyhow::{Result, bail};
+use serde::Serialize;
 
-pub async fn login_command() -> Result<()> {
+/// What `imp login --output json|csv` prints.
+#[derive(Serialize)]
+struct LoginRecord<'a> {
+    profile: &'a str,
+    already_logged_in: bool,
+}
+
+pub async fn login_command(passkey: bool, use_browser: bool) -> Result<()> {
+    let profile = &config::get().profile;
     if token_manager::validate_and_refresh().await.is_ok() {
+        if output::is_machine() {
+            return output::print_record(&LoginRecord { profile, already_logged_in: true });
+        }
         println!("✅ Already logged in!");
         return Ok(());
     }
 
-    print!("Your email: ");
-    io::stdout().flush()?;
-    let mut email = String::new();
-    io::stdin().read_line(&mut email)?;
-    let email = email.trim();
+    let tokens = if passkey {
+        passkey_login().await?
+    } else if use_browser {
+        browser_login().await?
+    } else {
+        otp_login().await?
+    };
+
+    tokens::save_tokens(&tokens)?;
+
+    if let Some(device_key) = &tokens.device_key
+        && let Err(e) = auth::remember_device(&tokens.access_token, device_key, &device_name()).await
+    {
+        eprintln!("Warning: couldn't register this device: {}", e);
+    }
+
+    if output::is_machine() {
+        return output::print_record(&LoginRecord { profile, already_logged_in: false });
+    }
+    println!("✅ Successfully logged in!");
+
+    Ok(())
+}
+
+fn prompt_email() -> Result<String> {
+    output::ask("Your email:")
+}
+
+async fn otp_login() -> Result<Tokens> {
+    let email = prompt_email()?;
 
-    println!("Sending code to {}...", email);
-    let result = auth::send_otp(email).await?;
+    output::progress(format!("Sending code to {}...", email));
+    let result = auth::send_otp(&email).await?;
 
-    print!("Enter code from your email: ");
-    io::stdout().flush()?;
-    let mut code = String::new();
-    io::stdin().read_line(&mut code)?;
-    let code = code.trim();
+    let code = output::ask("Enter code from your email:")?;
 
-    println!("Verifying...");
-    let tokens = match result {
-        OtpResult::Session(session) => auth::verify_otp(email, code, &session).await?,
+    output::progress("Verifying...");
+    match result {
+        OtpResult::Session(session) => auth::verify_otp(&email, &code, &session).await,
         OtpResult::NeedsConfirmation { session } => {
-            auth::confirm_signup_and_auth(email, code, &session).await?
+            auth::confirm_signup_and_auth(&email, &code, &session).await
         }
-    };
+    }
+}
 
-    tokens::save_tokens(&tokens)?;
+async fn passkey_login() -> Result<Tokens> {
+    // With a single local passkey we already know who is signing in
+    let local = authenticator::load_credentials()?;
+    let username = match local.as_slice() {
+        [only] if !only.user_name.is_empty() => only.user_name.clone(),
+        _ => prompt_email()?,
+    };
 
-    println!("✅ Successfully logged in!");
+    output::progress(format!("Signing in as {} with passkey...", username));
+    let (session, options) = auth::start_passkey_auth(&username).await?;
+    let credential = authenticator::get_assertion(&options)?;
 
-    Ok(())
+    auth::verify_passkey(&username, &session, &credential).await
 }
 
+async fn browser_login() -> Result<Tokens> {
+    let config = config::get();
+    let Some(auth_domain) = &config.auth_domain else {
+        bail!(
+            "Browser login isn't set up for profile '{}'. Set auth_domain for it in the config file, or sign in with: imp login",
+            config.profile
+        );
+    };
+    if !browser::browser_available() {
+        output::progress("No browser available, using an email code instead.");
+        return otp_login().await;
+    }
+
+    match browser::login(auth_domain).await? {
+        Some(tokens) => Ok(tokens),
+        None => {
+            output::progress("Couldn't open a browser, using an email code instead.");
+            otp_login().await
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use crate::auth::{auth, tokens};
+use crate::output;
 use anyhow::{bail, Result};
 
 pub async fn logout_command(all: bool) -> Result<()> {
@@ -8,10 +9,10 @@ pub async fn logout_command(all: bool) -> Result<()> {
     };
     if all {
         auth::global_logout(&tokens.access_token).await?;
-        println!("✅ Logged out from all devices!");
+        output::say("✅ Logged out from all devices!");
     } else {
         auth::logout(&tokens.refresh_token).await?;
-        println!("✅ Logged out successfully!");
+        output::say("✅ Logged out successfully!");
     }
     
     tokens::delete_tokens().ok();
@@ -1,3 +1,4 @@
+pub mod analyze;
 pub mod log;
 pub mod login;
 pub mod logout;
@@ -6,5 +7,10 @@ pub mod devices;
 pub mod plan;
 pub mod confirm;
 pub mod view;
+pub mod sync;
+pub mod profile;
+pub mod prompt;
+pub mod remind;
+pub mod status;
 pub mod update;
 pub mod uninstall;
@@ -1,5 +1,83 @@
-use anyhow::Result;
+use anyhow::{Result, bail};
+use chrono::Local;
+use colored::Colorize;
+use crate::auth::{auth, authenticator, token_manager};
+use crate::output;
+
+pub async fn passkey_register_command() -> Result<()> {
+    let access_token = token_manager::get_valid_token().await?;
+
+    output::progress("Creating a passkey on this machine...");
+    let options = auth::start_passkey_registration(&access_token).await?;
+    let (credential, stored, private_key) = authenticator::make_credential(&options)?;
+
+    auth::complete_passkey_registration(&access_token, &credential).await?;
+    authenticator::save_credential(stored.clone(), &private_key)?;
+
+    if output::is_machine() {
+        return output::print_record(&stored);
+    }
+
+    println!("{} Passkey registered", "✓".bright_green());
+    println!("Sign in with: imp login --passkey");
+
+    Ok(())
+}
+
+pub async fn passkey_list_command() -> Result<()> {
+    let access_token = token_manager::get_valid_token().await?;
+    let local = authenticator::load_credentials()?;
+
+    let mut passkeys = auth::list_passkeys(&access_token).await?;
+    for passkey in &mut passkeys {
+        passkey.on_this_machine = local.iter().any(|c| c.credential_id == passkey.credential_id);
+    }
+
+    if output::is_machine() {
+        return output::print_records(&passkeys);
+    }
+
+    if passkeys.is_empty() {
+        println!("{}", "No passkeys. Add one with: imp passkey register".dimmed());
+        return Ok(());
+    }
+
+    for passkey in passkeys {
+        let marker = if passkey.on_this_machine { "*".bright_green() } else { " ".normal() };
+        let created = passkey
+            .created_at
+            .map(|t| t.with_timezone(&Local).format("%Y-%m-%d").to_string())
+            .unwrap_or_else(|| "-".to_string());
+        println!(
+            "{} {:<28} {:<10} {}  {}",
+            marker,
+            passkey.name,
+            created,
+            passkey.attachment.as_deref().unwrap_or("-").dimmed(),
+            passkey.credential_id.dimmed(),
+        );
+    }
+
+    Ok(())
+}
+
+pub async fn passkey_remove_command(credential_id: &str, yes: bool) -> Result<()> {
+    let access_token = token_manager::get_valid_token().await?;
+
+    let passkeys = auth::list_passkeys(&access_token).await?;
+    let Some(passkey) = passkeys.into_iter().find(|p| p.credential_id == credential_id) else {
+        bail!("Passkey {} not found. Run: imp passkey list", credential_id);
+    };
+
+    if !yes && !output::confirm(format!("Remove passkey {}?", passkey.name.bright_white()))? {
+        output::progress("Cancelled".dimmed());
+        return Ok(());
+    }
+
+    auth::delete_passkey(&access_token, &passkey.credential_id).await?;
+    authenticator::remove_credential(&passkey.credential_id)?;
+
+    output::say(format!("{} Removed passkey {}", "✓".bright_green(), passkey.name));
 
-pub fn remove_passkey_command(device_id: &String) -> Result<()> {
     Ok(())
 }
@@ -1,28 +1,268 @@
-use anyhow::Result;
+use anyhow::{Context, Result, bail};
+use chrono::{Local, NaiveDate};
 use colored::Colorize;
-use crate::auth::token_manager;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use crate::agenda;
+use crate::api::client::ApiClient;
+use crate::api::types::{LogRequest, Plan, PlanPauseRequest, PlanStatus, PlanUpdateRequest};
+use crate::cache;
+use crate::charts;
+use crate::error::ImpError;
+use crate::output;
+use crate::parse::cadence::{Cadence, parse_cadence, plan_cadence};
+use crate::time;
 
-pub async fn plan_command(goal: Vec<String>) -> Result<()> {
-    let token = token_manager::get_valid_token().await?;
-    let text = format!("{} #plan", goal.join(" "));
+#[derive(Serialize)]
+struct PlanRecord {
+    id: Option<String>,
+    goal: String,
+    cadence: Option<Cadence>,
+}
+
+pub async fn plan_command(goal: Vec<String>, yes: bool) -> Result<()> {
+    let goal = goal.join(" ").trim().to_string();
+    if goal.is_empty() {
+        bail!("Describe the plan, e.g. imp plan \"10 pullups every day\"");
+    }
+    let cadence = parse_cadence(&goal)?;
+    if let Some(until) = cadence.as_ref().and_then(|c| c.until)
+        && until < Local::now().date_naive()
+    {
+        bail!("The plan would end {}, which has already passed", until);
+    }
+
+    if !output::is_machine() {
+        println!("{} {}", "Creating plan:".bright_green().bold(), goal.cyan());
+        match &cadence {
+            Some(cadence) => println!("{} {}", "Schedule:".dimmed(), cadence),
+            None => println!("{}", "Schedule: not recognized, the server will read it from the text".dimmed()),
+        }
+        // Scripts can't answer, so machine output implies --yes. A schedule
+        // imp couldn't read defaults to no, since the server may read it differently
+        let create = yes
+            || match cadence {
+                Some(_) => output::confirm_default_yes("Create this plan?")?,
+                None => output::confirm("Create it anyway?")?,
+            };
+        if !create {
+            println!("{}", "Cancelled".dimmed());
+            return Ok(());
+        }
+    }
+
+    let api = ApiClient::authenticated().await?;
+    let text = format!("{} #plan", goal);
+    let request = LogRequest { raw_input: text, occurred_at: None, note: None, parsed: None, cadence: cadence.clone() };
+    let id = api.log(&request, None)
+        .await
+        .map_err(ImpError::from)
+        .context("Failed to create plan")?;
+    cache::invalidate()?;
+
+    if output::is_machine() {
+        return output::print_record(&PlanRecord { id, goal, cadence });
+    }
+    let shown = id.as_deref().map(|id| format!(" {}", id.dimmed())).unwrap_or_default();
+    println!("{}{}", "✓ Plan created".bright_green(), shown);
+
+    Ok(())
+}
+
+pub async fn plan_list_command(all: bool) -> Result<()> {
+    let api = ApiClient::authenticated().await?;
+    let plans = api.plans(all)
+        .await
+        .map_err(ImpError::from)
+        .context("Failed to get plans")?
+        .plans;
+
+    if output::is_machine() {
+        return output::print_records(&plans);
+    }
+
+    if plans.is_empty() {
+        println!("{}", "No plans. Make one with: imp plan \"10 pullups every day\"".dimmed());
+        return Ok(());
+    }
+
+    let width = plans.iter().map(|p| p.goal.chars().count()).max().unwrap_or(0).min(40);
+    for plan in &plans {
+        let cadence = plan.cadence.as_deref().unwrap_or("-");
+        let progress = plan
+            .progress
+            .as_ref()
+            .map(|p| format!("{}/{} {}", p.done, p.target, p.period))
+            .unwrap_or_default();
+        let line = format!("{} {:<width$}  {:<14} {}", short_id(plan).dimmed(), plan.goal, cadence, progress)
+            .trim_end()
+            .to_string();
+        match plan.status {
+            PlanStatus::Active => println!("{}", line),
+            PlanStatus::Paused => println!("{}  {}", line, describe_status(plan).yellow()),
+            PlanStatus::Archived => println!("{}", format!("{}  archived", line).dimmed()),
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn plan_show_command(id: &str) -> Result<()> {
+    let api = ApiClient::authenticated().await?;
+    let plan = find_plan(&api, id).await?;
+    let detail = api.plan(plan.id)
+        .await
+        .map_err(ImpError::from)
+        .context("Failed to get plan")?;
+
+    if output::is_machine() {
+        return output::print_record(&detail);
+    }
+
+    let plan = &detail.plan;
+    println!("{}", plan.goal.bold());
+    println!("{} {}", "Id:      ".dimmed(), plan.id);
+    println!("{} {}", "Status:  ".dimmed(), describe_status(plan));
+    if let Some(cadence) = &plan.cadence {
+        println!("{} {}", "Cadence: ".dimmed(), cadence);
+    }
+    if let Some(target) = plan.target {
+        println!("{} {}", "Target:  ".dimmed(), target);
+    }
+    if let Some(progress) = &plan.progress {
+        println!("{} {} of {} {}", "Progress:".dimmed(), progress.done, progress.target, progress.period);
+    }
+
+    let times: Vec<_> = detail.occurrences.iter().filter_map(|o| o.time()).map(|t| t.with_timezone(&Local)).collect();
+    let days: BTreeSet<NaiveDate> = times.iter().map(|t| t.date_naive()).collect();
+    // Kept to the schedule, as in `imp view stats`
+    let today = Local::now().date_naive();
+    let ((current, longest), unit) = match plan_cadence(&plan.goal, plan.cadence.as_deref()) {
+        Some(cadence) => (agenda::plan_streaks(&cadence.recurrence, &days, today), agenda::streak_unit(&cadence.recurrence)),
+        None => (charts::streaks(&days, today), "day(s)"),
+    };
+    println!("{} {} {}, best {}", "Streak:  ".dimmed(), current, unit, longest);
+    println!("{} {}", "Created: ".dimmed(), plan.created_at.with_timezone(&Local).format("%Y-%m-%d"));
 
-    println!("{} {}", "Creating plan:".bright_green().bold(), goal.join(" ").cyan());
+    println!("\n{}", "History".bold());
+    if detail.occurrences.is_empty() {
+        println!("  {}", "Nothing logged towards this plan yet".dimmed());
+    }
+    for occurrence in &detail.occurrences {
+        let when = occurrence
+            .time()
+            .map(|t| t.with_timezone(&Local).format("%a %Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|| occurrence.timestamp.clone());
+        println!("  {} - {}", when.dimmed(), occurrence.text);
+    }
+
+    Ok(())
+}
+
+pub async fn plan_edit_command(id: &str, goal: Option<String>, target: Option<u32>) -> Result<()> {
+    let goal = goal.map(|g| g.trim().to_string());
+    if goal.as_deref() == Some("") {
+        bail!("A plan's goal can't be empty");
+    }
+    if goal.is_none() && target.is_none() {
+        bail!("Nothing to change. Pass --goal and/or --target");
+    }
+
+    let api = ApiClient::authenticated().await?;
+    let plan = find_plan(&api, id).await?;
+    let updated = api.update_plan(plan.id, &PlanUpdateRequest { goal, target })
+        .await
+        .map_err(ImpError::from)
+        .context("Failed to update plan")?;
+
+    print_updated(&updated, "Plan updated")
+}
+
+pub async fn plan_pause_command(id: &str, from: Option<String>, until: Option<String>) -> Result<()> {
+    let from = match from {
+        Some(from) => time::parse_schedule_day(&from)?,
+        None => Local::now().date_naive(),
+    };
+    let until = until.as_deref().map(time::parse_schedule_day).transpose()?;
+    if until.is_some_and(|until| until < from) {
+        bail!("--until is before the pause starts ({})", from);
+    }
+
+    let api = ApiClient::authenticated().await?;
+    let plan = find_plan(&api, id).await?;
+    let updated = api.pause_plan(plan.id, &PlanPauseRequest { from, until })
+        .await
+        .map_err(ImpError::from)
+        .context("Failed to pause plan")?;
+
+    print_updated(&updated, "Plan paused")
+}
+
+pub async fn plan_resume_command(id: &str) -> Result<()> {
+    let api = ApiClient::authenticated().await?;
+    let plan = find_plan(&api, id).await?;
+    let updated = api.resume_plan(plan.id)
+        .await
+        .map_err(ImpError::from)
+        .context("Failed to resume plan")?;
+
+    print_updated(&updated, "Plan resumed")
+}
 
-    let client = reqwest::Client::new();
-    let response = client
-        .post("https://api.iepok.com/log")
-        .bearer_auth(token)
-        .json(&serde_json::json!({ "raw_input": text }))
-        .send()
-        .await?;
+pub async fn plan_archive_command(id: &str) -> Result<()> {
+    let api = ApiClient::authenticated().await?;
+    let plan = find_plan(&api, id).await?;
+    let updated = api.archive_plan(plan.id)
+        .await
+        .map_err(ImpError::from)
+        .context("Failed to archive plan")?;
 
-    if !response.status().is_success() {
-        let status = response.status();
-        let body = response.text().await?;
-        anyhow::bail!("Failed to create plan: {} - {}", status, body);
+    print_updated(&updated, "Plan archived")
+}
+
+/// Finds a plan by its id or any unambiguous prefix of it, like git commits.
+async fn find_plan(api: &ApiClient, id: &str) -> Result<Plan> {
+    let prefix = id.trim().to_lowercase();
+    if prefix.is_empty() {
+        bail!("Give a plan id as shown by: imp plan list");
     }
 
-    println!("{}", "✓ Plan created".bright_green());
+    let plans = api.plans(true)
+        .await
+        .map_err(ImpError::from)
+        .context("Failed to get plans")?
+        .plans;
+    let mut matches = plans.into_iter().filter(|p| p.id.to_string().starts_with(&prefix));
 
+    match (matches.next(), matches.next()) {
+        (Some(plan), None) => Ok(plan),
+        (Some(_), Some(_)) => bail!("'{}' matches several plans, use more of the id", id),
+        (None, _) => bail!("Plan {} not found. Run: imp plan list --all", id),
+    }
+}
+
+fn short_id(plan: &Plan) -> String {
+    plan.id.to_string()[..8].to_string()
+}
+
+fn describe_status(plan: &Plan) -> String {
+    match (plan.status, plan.paused_from, plan.paused_until) {
+        (PlanStatus::Paused, Some(from), Some(until)) => format!("paused {} to {}", from, until),
+        (PlanStatus::Paused, Some(from), None) => format!("paused since {}", from),
+        (PlanStatus::Paused, None, Some(until)) => format!("paused until {}", until),
+        (PlanStatus::Paused, None, None) => "paused".to_string(),
+        (PlanStatus::Active, Some(from), _) if from > Local::now().date_naive() => format!("active, pauses from {}", from),
+        (PlanStatus::Active, ..) => "active".to_string(),
+        (PlanStatus::Archived, ..) => "archived".to_string(),
+    }
+}
+
+fn print_updated(plan: &Plan, message: &str) -> Result<()> {
+    // Reminders read plans from the cache
+    cache::invalidate()?;
+    if output::is_machine() {
+        return output::print_record(plan);
+    }
+    println!("{} {} {}", format!("✓ {}:", message).bright_green(), plan.goal, format!("({})", describe_status(plan)).dimmed());
     Ok(())
 }
@@ -0,0 +1,134 @@
+use anyhow::{Result, bail};
+use colored::Colorize;
+use serde::Serialize;
+use std::fs;
+use crate::auth::tokens;
+use crate::config::{self, ConfigFile, ProfileConfig, DEFAULT_PROFILE};
+use crate::{output, queue};
+
+fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        bail!("Profile names may only contain letters, digits, '-' and '_'");
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct ProfileRecord<'a> {
+    name: String,
+    active: bool,
+    signed_in: bool,
+    api_url: &'a str,
+}
+
+pub fn profile_list_command() -> Result<()> {
+    let file = config::load_config_file()?;
+    let active = &config::get().profile;
+
+    let names = std::iter::once(DEFAULT_PROFILE.to_string())
+        .chain(file.profiles.keys().filter(|n| *n != DEFAULT_PROFILE).cloned());
+
+    let profiles: Vec<ProfileRecord> = names.map(|name| profile_record(&file, &name, &name == active)).collect();
+
+    if output::is_machine() {
+        return output::print_records(&profiles);
+    }
+
+    for profile in profiles {
+        let marker = if profile.active { "*".bright_green() } else { " ".normal() };
+        let state = if profile.signed_in { "signed in".green() } else { "signed out".dimmed() };
+        println!("{} {:<16} {:<11} {}", marker, profile.name, state, profile.api_url.dimmed());
+    }
+
+    Ok(())
+}
+
+fn profile_record<'a>(file: &'a ConfigFile, name: &str, active: bool) -> ProfileRecord<'a> {
+    ProfileRecord {
+        name: name.to_string(),
+        active,
+        signed_in: tokens::has_tokens(name),
+        api_url: file.profiles
+            .get(name)
+            .and_then(|p| p.api_url.as_deref())
+            .or(file.defaults.api_url.as_deref())
+            .unwrap_or(config::DEFAULT_API_URL),
+    }
+}
+
+pub fn profile_add_command(name: &str, profile: ProfileConfig) -> Result<()> {
+    validate_name(name)?;
+    let mut file = config::load_config_file()?;
+    if name == DEFAULT_PROFILE || file.profiles.contains_key(name) {
+        bail!("Profile '{}' already exists", name);
+    }
+
+    file.profiles.insert(name.to_string(), profile);
+    config::save_config_file(&file)?;
+
+    if output::is_machine() {
+        return output::print_record(&profile_record(&file, name, false));
+    }
+    println!("{} Added profile '{}'", "✓".bright_green(), name);
+    println!("Sign in with: imp --profile {} login", name);
+
+    Ok(())
+}
+
+pub fn profile_switch_command(name: &str) -> Result<()> {
+    let mut file = config::load_config_file()?;
+    if name != DEFAULT_PROFILE && !file.profiles.contains_key(name) {
+        bail!("Profile '{}' not found. Create it with: imp profile add {}", name, name);
+    }
+
+    file.active_profile = (name != DEFAULT_PROFILE).then(|| name.to_string());
+    config::save_config_file(&file)?;
+
+    if output::is_machine() {
+        return output::print_record(&profile_record(&file, name, true));
+    }
+    println!("{} Switched to profile '{}'", "✓".bright_green(), name);
+    if !tokens::has_tokens(name) {
+        println!("Not signed in yet. Run: imp login");
+    }
+
+    Ok(())
+}
+
+pub fn profile_remove_command(name: &str, force: bool) -> Result<()> {
+    if name == DEFAULT_PROFILE {
+        bail!("The default profile can't be removed");
+    }
+    let mut file = config::load_config_file()?;
+    if !file.profiles.contains_key(name) {
+        bail!("Profile '{}' not found", name);
+    }
+
+    let queued = queue::load_queue_for(name)?.len();
+    if queued > 0 && !force {
+        bail!(
+            "Profile '{}' has {} queued log(s). Send them with: imp --profile {} sync, or remove it anyway with --force",
+            name, queued, name
+        );
+    }
+
+    // Before the config goes, since it says which store the tokens are in
+    tokens::delete_tokens_for(name)?;
+    tokens::delete_passkey_keys_for(name)?;
+
+    file.profiles.remove(name);
+    if file.active_profile.as_deref() == Some(name) {
+        file.active_profile = None;
+    }
+    config::save_config_file(&file)?;
+
+    // Drops the cache, state and any logs still queued for it
+    let dir = config::profile_dir_for(name);
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+
+    output::say(format!("{} Removed profile '{}'", "✓".bright_green(), name));
+
+    Ok(())
+}
@@ -0,0 +1,177 @@
+//! A one-line progress segment for shell prompts and status bars. It only reads
+//! local files, never the network, so it can run on every prompt.
+
+use anyhow::Result;
+use chrono::{Duration, Local, Utc};
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::json;
+use std::env;
+use std::process::{Command, Stdio};
+use crate::agenda::{self, Today};
+use crate::cache;
+use crate::commands::sync;
+use crate::{config, output, queue, state};
+
+/// "3/5 ✓ 🔥12"; parts with nothing to show are left out.
+const DEFAULT_FORMAT: &str = "{progress} {check} {fire}";
+
+/// Don't start another background refresh within this long of the last one.
+const REFRESH_BACKOFF_SECS: i64 = 60;
+
+/// Where the segment is shown, which decides how it's colored.
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum PromptTarget {
+    /// No colors; also what starship custom modules and tmux want
+    #[default]
+    Plain,
+    Bash,
+    Zsh,
+    /// A JSON object for a waybar custom module
+    Waybar,
+    /// Text with polybar color tags
+    Polybar,
+}
+
+#[derive(Clone, Copy)]
+enum Mood {
+    /// Every plan for today is done.
+    Done,
+    /// Something is still due.
+    Due,
+    /// No plans today.
+    Idle,
+}
+
+/// What `imp prompt --output json|csv` prints.
+#[derive(Serialize)]
+struct PromptRecord {
+    done: usize,
+    total: usize,
+    due: Vec<String>,
+    streak: u32,
+    queued: usize,
+    /// Cached data is older than a few minutes; a refresh was started.
+    stale: bool,
+}
+
+pub async fn prompt_command(target: PromptTarget, format: Option<String>, refresh: bool) -> Result<()> {
+    if refresh {
+        sync::refresh_cache().await;
+        return Ok(());
+    }
+
+    let cache = cache::load_cache();
+    let stale = !cache.plans_fresh() || !cache.occurrences_fresh();
+    if stale {
+        refresh_in_background();
+    }
+    let today = agenda::plans_today(&cache, Local::now().date_naive())?;
+    let queued = queue::load_queue()?.len();
+
+    if output::is_machine() {
+        return output::print_record(&PromptRecord {
+            done: today.done.len(),
+            total: today.done.len() + today.due.len(),
+            due: today.due.iter().map(|plan| plan.goal.clone()).collect(),
+            streak: today.streak,
+            queued,
+            stale,
+        });
+    }
+
+    let text = render(format.as_deref().unwrap_or(DEFAULT_FORMAT), &today, queued);
+    let mood = if !today.due.is_empty() {
+        Mood::Due
+    } else if !today.done.is_empty() {
+        Mood::Done
+    } else {
+        Mood::Idle
+    };
+    println!("{}", paint(&text, mood, target, &today));
+    Ok(())
+}
+
+/// Fills in a template such as "{done}/{total} 🔥{streak}".
+fn render(template: &str, today: &Today, queued: usize) -> String {
+    let done = today.done.len();
+    let total = done + today.due.len();
+    let fields = [
+        ("{done}", done.to_string()),
+        ("{total}", total.to_string()),
+        ("{due}", today.due.len().to_string()),
+        ("{streak}", today.streak.to_string()),
+        ("{queued}", queued.to_string()),
+        ("{progress}", if total > 0 { format!("{}/{}", done, total) } else { String::new() }),
+        ("{check}", if total > 0 && done == total { "✓".to_string() } else { String::new() }),
+        ("{fire}", if today.streak > 0 { format!("🔥{}", today.streak) } else { String::new() }),
+    ];
+    let filled = fields.iter().fold(template.to_string(), |text, (key, value)| text.replace(key, value));
+    // Empty parts would otherwise leave gaps
+    filled.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn paint(text: &str, mood: Mood, target: PromptTarget, today: &Today) -> String {
+    if text.is_empty() && target != PromptTarget::Waybar {
+        return String::new();
+    }
+    let no_color = env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    let (ansi, name, hex) = match mood {
+        Mood::Done => ("32", "green", "#50fa7b"),
+        Mood::Due => ("33", "yellow", "#f1fa8c"),
+        Mood::Idle => ("0", "default", ""),
+    };
+
+    match target {
+        PromptTarget::Plain => text.to_string(),
+        _ if no_color && target != PromptTarget::Waybar => text.to_string(),
+        // \x01 and \x02 tell readline the escapes take no space, which `\[ \]`
+        // can't do from inside a command substitution
+        PromptTarget::Bash => format!("\x01\x1b[{}m\x02{}\x01\x1b[0m\x02", ansi, text),
+        PromptTarget::Zsh => format!("%F{{{}}}{}%f", name, text.replace('%', "%%")),
+        PromptTarget::Polybar if hex.is_empty() => text.to_string(),
+        PromptTarget::Polybar => format!("%{{F{}}}{}%{{F-}}", hex, text),
+        PromptTarget::Waybar => {
+            let total = today.done.len() + today.due.len();
+            let tooltip: Vec<String> = today
+                .due
+                .iter()
+                .map(|plan| format!("○ {}", plan.goal))
+                .chain(today.done.iter().map(|plan| format!("✓ {}", plan.goal)))
+                .collect();
+            json!({
+                "text": text,
+                "tooltip": tooltip.join("\n"),
+                "class": match mood {
+                    Mood::Done => "done",
+                    Mood::Due => "due",
+                    Mood::Idle => "idle",
+                },
+                "percentage": (today.done.len() * 100).checked_div(total).unwrap_or(0),
+            })
+            .to_string()
+        }
+    }
+}
+
+/// Starts `imp prompt --refresh` detached so this prompt isn't kept waiting.
+/// The next prompt shows what it fetched.
+fn refresh_in_background() {
+    let mut state = state::load_state();
+    if state.refresh_started_at.is_some_and(|at| Utc::now() - at < Duration::seconds(REFRESH_BACKOFF_SECS)) {
+        return;
+    }
+    let Ok(exe) = env::current_exe() else {
+        return;
+    };
+    let spawned = Command::new(exe)
+        .args(["--profile", &config::get().profile, "prompt", "--refresh"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    if spawned.is_ok() {
+        state.refresh_started_at = Some(Utc::now());
+        let _ = state::save_state(&state);
+    }
+}
@@ -0,0 +1,271 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, Utc};
+use colored::Colorize;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::{env, fs};
+use std::path::PathBuf;
+use std::time::Duration;
+use zbus::zvariant::Value;
+use crate::agenda::{self, DuePlan};
+use crate::cache;
+use crate::commands::sync;
+use crate::config::{self, DEFAULT_PROFILE};
+use crate::{output, state, time};
+
+/// How often the service wakes up to see whether a reminder time has passed.
+const TICK: Duration = Duration::from_secs(60);
+
+/// What `imp remind --snooze --output json|csv` prints.
+#[derive(Serialize)]
+struct SnoozeRecord {
+    snoozed_until: Option<DateTime<Utc>>,
+}
+
+pub async fn remind_command(check: bool, install: bool, snooze: Option<String>) -> Result<()> {
+    if install {
+        return install_unit();
+    }
+    if let Some(snooze) = snooze {
+        return snooze_reminders(&snooze);
+    }
+    if check {
+        return check_due().await;
+    }
+    run().await
+}
+
+/// Prints today's due plans, for scripts and cron, and exits with how many.
+async fn check_due() -> Result<()> {
+    sync::refresh_cache().await;
+    let due = agenda::due_plans(&cache::load_cache(), Local::now().date_naive())?;
+
+    if output::is_machine() {
+        output::print_records(&due)?;
+    } else if due.is_empty() {
+        println!("{} Nothing due today", "✓".bright_green());
+    } else {
+        println!("{} {} due today:", "⏰".yellow(), plans_due(due.len()));
+        for plan in &due {
+            println!("  • {} {}", plan.goal, format!("({})", plan.schedule).dimmed());
+        }
+    }
+    std::process::exit(due_exit_code(due.len()));
+}
+
+/// The number of due plans, skipping past 3-10 where the error codes are
+/// (3 due exits 11) and stopping at 255, the highest exit code there is.
+fn due_exit_code(count: usize) -> i32 {
+    match count {
+        0..=2 => count as i32,
+        _ => count.saturating_add(8).min(255) as i32,
+    }
+}
+
+fn snooze_reminders(input: &str) -> Result<()> {
+    let mut state = state::load_state();
+    state.snoozed_until = match input.trim() {
+        "off" | "0" => None,
+        duration => Some(
+            Utc::now()
+                .checked_add_signed(time::parse_duration(duration)?)
+                .with_context(|| format!("Can't snooze for \"{}\", that's too long", duration))?,
+        ),
+    };
+    state::save_state(&state)?;
+
+    if output::is_machine() {
+        return output::print_record(&SnoozeRecord { snoozed_until: state.snoozed_until });
+    }
+    match state.snoozed_until {
+        Some(until) => println!(
+            "{} Reminders snoozed until {}",
+            "✓".bright_green(),
+            until.with_timezone(&Local).format("%a %H:%M")
+        ),
+        None => println!("{} Reminders back on", "✓".bright_green()),
+    }
+    Ok(())
+}
+
+/// Runs until stopped, notifying once per reminder time when plans are due.
+async fn run() -> Result<()> {
+    let config = config::get();
+    if config.remind_at.is_empty() {
+        anyhow::bail!("No reminder times set. Add remind_at = [\"09:00\", \"19:00\"] to config.toml");
+    }
+    let times: Vec<String> = config.remind_at.iter().map(|t| t.format("%H:%M").to_string()).collect();
+    eprintln!("Reminding at {} (Ctrl-C to stop)", times.join(", "));
+
+    loop {
+        if let Err(e) = tick().await {
+            eprintln!("{} {:#}", "✗".red(), e);
+        }
+        tokio::time::sleep(TICK).await;
+    }
+}
+
+/// Notifies for the latest reminder time that has passed today, unless it was
+/// already handled. One that falls in quiet hours or a snooze goes out when they end.
+async fn tick() -> Result<()> {
+    let config = config::get();
+    let now = Local::now();
+    let Some(slot) = config.remind_at.iter().rev().find(|t| **t <= now.time()) else {
+        return Ok(());
+    };
+    let Some(slot) = now.date_naive().and_time(*slot).and_local_timezone(Local).earliest() else {
+        return Ok(());
+    };
+
+    let mut state = state::load_state();
+    if state.last_reminder.is_some_and(|at| at >= slot)
+        || state.snoozed_until.is_some_and(|until| until > Utc::now())
+        || config.is_quiet(now.time())
+    {
+        return Ok(());
+    }
+
+    sync::refresh_cache().await;
+    let due = agenda::due_plans(&cache::load_cache(), now.date_naive())?;
+    // Recorded first so a broken notification daemon doesn't mean a retry every minute
+    state.last_reminder = Some(Utc::now());
+    state::save_state(&state)?;
+    if due.is_empty() {
+        return Ok(());
+    }
+
+    state.notification_id = Some(notify(&due, state.notification_id).await?);
+    state::save_state(&state)
+}
+
+/// Sends a desktop notification over D-Bus, replacing the previous one if it's still shown.
+async fn notify(due: &[DuePlan], replaces: Option<u32>) -> Result<u32> {
+    let summary = format!("{} due today", plans_due(due.len()));
+    let body = due.iter().map(|plan| format!("• {}", plan.goal)).collect::<Vec<_>>().join("\n");
+
+    let connection = zbus::Connection::session()
+        .await
+        .context("No desktop session bus to send notifications on")?;
+    let reply = connection
+        .call_method(
+            Some("org.freedesktop.Notifications"),
+            "/org/freedesktop/Notifications",
+            Some("org.freedesktop.Notifications"),
+            "Notify",
+            &(
+                "imp",
+                replaces.unwrap_or(0),
+                "",
+                summary.as_str(),
+                body.as_str(),
+                Vec::<&str>::new(),
+                HashMap::<&str, Value>::new(),
+                -1i32,
+            ),
+        )
+        .await
+        .context("Failed to show notification")?;
+    Ok(reply.body().deserialize::<u32>()?)
+}
+
+fn plans_due(count: usize) -> String {
+    if count == 1 { "1 plan".to_string() } else { format!("{} plans", count) }
+}
+
+fn unit_dir() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("systemd").join("user"))
+}
+
+/// The systemd user unit for the current profile, e.g. `imp-remind-work.service`.
+fn unit_path() -> Option<PathBuf> {
+    let profile = &config::get().profile;
+    let name = if profile == DEFAULT_PROFILE {
+        "imp-remind.service".to_string()
+    } else {
+        format!("imp-remind-{}.service", profile)
+    };
+    Some(unit_dir()?.join(name))
+}
+
+/// Every profile's installed unit.
+pub fn installed_units() -> Vec<PathBuf> {
+    let Some(entries) = unit_dir().and_then(|dir| fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("imp-remind") && name.ends_with(".service"))
+        })
+        .collect()
+}
+
+/// Quotes a word for a unit's `ExecStart=`, so paths with spaces, quotes,
+/// `%` specifiers or `$` variables are passed through as they are.
+fn quote_exec_arg(arg: &str) -> String {
+    let escaped = arg
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%")
+        .replace('$', "$$");
+    format!("\"{}\"", escaped)
+}
+
+fn install_unit() -> Result<()> {
+    let path = unit_path().context("Can't find the config directory")?;
+    let exe = env::current_exe().context("Can't find the imp binary")?;
+    let profile = &config::get().profile;
+    let profile_arg = if profile == DEFAULT_PROFILE {
+        String::new()
+    } else {
+        format!(" --profile {}", quote_exec_arg(profile))
+    };
+
+    let unit = format!(
+        "[Unit]\n\
+         Description=imp plan reminders\n\
+         PartOf=graphical-session.target\n\
+         After=graphical-session.target\n\
+         \n\
+         [Service]\n\
+         ExecStart={}{} remind\n\
+         Restart=on-failure\n\
+         RestartSec=30\n\
+         \n\
+         [Install]\n\
+         WantedBy=graphical-session.target\n",
+        quote_exec_arg(&exe.to_string_lossy()),
+        profile_arg
+    );
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(&path, unit).with_context(|| format!("Failed to write {}", path.display()))?;
+
+    println!("{} Wrote {}", "✓".bright_green(), path.display());
+    println!("Start it now and at every login with:");
+    println!("  systemctl --user daemon-reload");
+    println!("  systemctl --user enable --now {}", path.file_name().unwrap().to_string_lossy());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn due_counts_avoid_error_codes() {
+        assert_eq!(due_exit_code(0), 0);
+        assert_eq!(due_exit_code(2), 2);
+        assert_eq!(due_exit_code(3), 11);
+        assert_eq!(due_exit_code(500), 255);
+    }
+
+    #[test]
+    fn exec_args_are_quoted() {
+        assert_eq!(quote_exec_arg("/home/me/.local/bin/imp"), "\"/home/me/.local/bin/imp\"");
+        assert_eq!(quote_exec_arg("/home/Jo Doe/bin/imp"), "\"/home/Jo Doe/bin/imp\"");
+        assert_eq!(quote_exec_arg(r#"C:\my "apps"\100%$"#), r#""C:\\my \"apps\"\\100%%$$""#);
+    }
+}
@@ -0,0 +1,117 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Local, Utc};
+use colored::Colorize;
+use serde::Serialize;
+use crate::auth::{jwk, tokens};
+use crate::{config, output, queue, state};
+
+/// Cognito's default refresh token validity. The token itself is opaque, so its
+/// expiry can only be estimated from when the user authenticated.
+const REFRESH_TOKEN_VALIDITY_DAYS: i64 = 30;
+
+#[derive(Serialize)]
+struct StatusReport {
+    profile: String,
+    api_url: String,
+    logged_in: bool,
+    token_store: &'static str,
+    /// Set when tokens are stored but couldn't be read, e.g. a wrong passphrase.
+    token_error: Option<String>,
+    email: Option<String>,
+    username: Option<String>,
+    access_token_expires_at: Option<DateTime<Utc>>,
+    refresh_token_expires_at: Option<DateTime<Utc>>,
+    last_sync: Option<DateTime<Utc>>,
+    queued: usize,
+}
+
+fn build_report() -> StatusReport {
+    let config = config::get();
+    let (tokens, token_error) = match tokens::load_tokens() {
+        Ok(tokens) => (Some(tokens), None),
+        Err(e) if tokens::has_tokens(&config.profile) => (None, Some(e.to_string())),
+        Err(_) => (None, None),
+    };
+    let id_claims = tokens.as_ref().and_then(|t| jwk::read_claims(&t.id_token).ok());
+    let access_claims = tokens.as_ref().and_then(|t| jwk::read_claims(&t.access_token).ok());
+
+    StatusReport {
+        profile: config.profile.clone(),
+        api_url: config.api_url.clone(),
+        logged_in: tokens.is_some() || token_error.is_some(),
+        token_store: tokens::store_name(),
+        token_error,
+        email: id_claims.as_ref().and_then(|c| c.email.clone()),
+        username: id_claims.as_ref().and_then(|c| c.username.clone()),
+        access_token_expires_at: access_claims.and_then(|c| DateTime::from_timestamp(c.exp, 0)),
+        refresh_token_expires_at: id_claims
+            .and_then(|c| c.auth_time)
+            .and_then(|t| DateTime::from_timestamp(t, 0))
+            .map(|t| t + Duration::days(REFRESH_TOKEN_VALIDITY_DAYS)),
+        last_sync: state::load_state().last_sync,
+        queued: queue::load_queue().map(|q| q.len()).unwrap_or(0),
+    }
+}
+
+fn describe(at: DateTime<Utc>) -> String {
+    let local = at.with_timezone(&Local).format("%Y-%m-%d %H:%M");
+    let delta = at - Utc::now();
+    if delta < Duration::zero() {
+        format!("{} ({} ago)", local, humanize(-delta))
+    } else {
+        format!("{} (in {})", local, humanize(delta))
+    }
+}
+
+fn humanize(delta: Duration) -> String {
+    if delta.num_days() > 0 {
+        format!("{}d", delta.num_days())
+    } else if delta.num_hours() > 0 {
+        format!("{}h", delta.num_hours())
+    } else {
+        format!("{}m", delta.num_minutes())
+    }
+}
+
+pub fn status_command() -> Result<()> {
+    let report = build_report();
+
+    if output::is_machine() {
+        return output::print_record(&report);
+    }
+
+    println!("{} {}", "Profile:".dimmed(), report.profile.bright_white());
+    println!("{} {}", "API:".dimmed(), report.api_url);
+
+    if !report.logged_in {
+        println!("{}", "Not logged in. Run: imp login".yellow());
+    } else if let Some(e) = &report.token_error {
+        println!("{} {}", "Tokens are stored but couldn't be read:".yellow(), e);
+    } else {
+        let who = report.email.as_deref().or(report.username.as_deref()).unwrap_or("unknown user");
+        println!("{} {}", "✓ Logged in as".bright_green(), who.bright_white());
+
+        if let Some(at) = report.access_token_expires_at {
+            let label = if at < Utc::now() { "expired, refreshed on next use" } else { "expires" };
+            println!("{} {} {}", "Access token:".dimmed(), label, describe(at));
+        }
+        if let Some(at) = report.refresh_token_expires_at {
+            println!("{} expires ~{}", "Refresh token:".dimmed(), describe(at));
+        }
+    }
+
+    if report.logged_in {
+        println!("{} {}", "Token store:".dimmed(), report.token_store);
+    }
+    match report.last_sync {
+        Some(at) => println!("{} {}", "Last sync:".dimmed(), describe(at)),
+        None => println!("{} never", "Last sync:".dimmed()),
+    }
+    if report.queued > 0 {
+        println!("{} {} (run: imp sync)", "Queued logs:".dimmed(), report.queued.to_string().yellow());
+    } else {
+        println!("{} 0", "Queued logs:".dimmed());
+    }
+
+    Ok(())
+}
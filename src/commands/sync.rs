@@ -0,0 +1,171 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use colored::Colorize;
+use serde::Serialize;
+use crate::api::client::{ApiClient, OccurrenceQuery};
+use crate::api::types::LogRequest;
+use crate::cache::{self, Cache};
+use crate::error::ImpError;
+use crate::parse::log::parse_log;
+use crate::{output, queue, state};
+
+pub struct ReplayReport {
+    pub sent: usize,
+    pub dropped: usize,
+    pub remaining: usize,
+    /// Why replay stopped early, if it did.
+    pub blocked_by: Option<anyhow::Error>,
+}
+
+/// Sends queued logs in capture order, stopping at the first one that can't be delivered.
+pub async fn replay_queue(api: &ApiClient) -> Result<ReplayReport> {
+    let mut pending = queue::load_queue()?;
+    let mut report = ReplayReport { sent: 0, dropped: 0, remaining: 0, blocked_by: None };
+
+    while let Some(entry) = pending.first() {
+        let request = LogRequest {
+            raw_input: entry.raw_input.clone(),
+            occurred_at: Some(entry.occurred_at.unwrap_or(entry.captured_at)),
+            note: entry.note.clone(),
+            parsed: parse_log(&entry.raw_input),
+            cadence: None,
+        };
+        match api.log(&request, Some(entry.key)).await {
+            Ok(_) => report.sent += 1,
+            Err(e) if e.is_transient() => {
+                report.blocked_by = Some(e.into());
+                break;
+            }
+            Err(e) => {
+                eprintln!("{} Dropping queued log \"{}\": {}", "✗".red(), entry.raw_input, e);
+                report.dropped += 1;
+            }
+        }
+        pending.remove(0);
+        // Persist after every send so a crash replays as little as possible.
+        // Whatever is replayed carries its key, so the server skips duplicates
+        queue::save_queue(&pending)?;
+    }
+
+    if report.sent > 0 {
+        cache::invalidate()?;
+    }
+    report.remaining = pending.len();
+    if report.remaining == 0 {
+        state::mark_synced()?;
+    }
+    Ok(report)
+}
+
+/// What `imp sync --output json|csv` prints.
+#[derive(Serialize)]
+struct SyncRecord {
+    sent: usize,
+    remaining: usize,
+    occurrences: usize,
+}
+
+/// Best-effort replay after a successful command; stays quiet unless something was sent.
+pub async fn flush_queue() {
+    if !queue::load_queue().is_ok_and(|q| !q.is_empty()) {
+        return;
+    }
+    let Ok(api) = ApiClient::authenticated().await else {
+        return;
+    };
+    if let Ok(report) = replay_queue(&api).await
+        && report.sent > 0
+        && !output::is_machine()
+    {
+        println!("{} Sent {} queued log(s)", "✓".bright_green(), report.sent);
+    }
+}
+
+/// Fetches what changed on the server since the last pull, or everything when `full`.
+/// The cursor is the server's, not the newest occurrence time, so logs backdated
+/// with `--at` or replayed late from another device still arrive, as do edits and deletes.
+pub async fn pull_occurrences(api: &ApiClient, cache: &mut Cache, full: bool) -> Result<()> {
+    let cursor = if full { None } else { cache.occurrences_cursor.clone() };
+    let fetched = api.occurrences(&OccurrenceQuery { cursor: cursor.clone(), ..Default::default() })
+        .await
+        .map_err(ImpError::from)
+        .context("Failed to get occurrences")?;
+
+    // Without a cursor on both sides the response is the whole history
+    if cursor.is_none() || fetched.cursor.is_none() {
+        cache.occurrences.clear();
+    }
+    cache.merge_occurrences(fetched.occurrences, &fetched.deleted);
+    cache.occurrences_cursor = fetched.cursor;
+    cache.occurrences_synced_at = Some(Utc::now());
+    Ok(())
+}
+
+pub async fn pull_summary(api: &ApiClient, cache: &mut Cache) -> Result<()> {
+    let fetched = api.summary()
+        .await
+        .map_err(ImpError::from)
+        .context("Failed to get view")?;
+
+    cache.summary = Some(fetched.summary);
+    cache.summary_synced_at = Some(Utc::now());
+    Ok(())
+}
+
+/// Active and paused plans, which reminders check without the network.
+pub async fn pull_plans(api: &ApiClient, cache: &mut Cache) -> Result<()> {
+    let fetched = api.plans(false)
+        .await
+        .map_err(ImpError::from)
+        .context("Failed to get plans")?;
+
+    cache.plans = fetched.plans;
+    cache.plans_synced_at = Some(Utc::now());
+    Ok(())
+}
+
+/// Brings plans and history up to date when the network allows, for commands
+/// that fall back to the cache otherwise.
+pub async fn refresh_cache() {
+    let mut cache = cache::load_cache();
+    if cache.plans_fresh() && cache.occurrences_fresh() {
+        return;
+    }
+    let Ok(api) = ApiClient::authenticated().await else {
+        return;
+    };
+    let _ = replay_queue(&api).await;
+    if pull_plans(&api, &mut cache).await.is_ok() && pull_occurrences(&api, &mut cache, false).await.is_ok() {
+        let _ = cache::save_cache(&cache);
+    }
+}
+
+/// Sends queued logs, then brings the local history up to date.
+pub async fn sync_command() -> Result<()> {
+    let api = ApiClient::authenticated().await?;
+
+    let queued = queue::load_queue()?.len();
+    let (mut sent, mut remaining) = (0, 0);
+    if queued > 0 {
+        let report = replay_queue(&api).await?;
+        (sent, remaining) = (report.sent, report.remaining);
+        if !output::is_machine() {
+            println!("{} Sent {} of {} queued log(s)", "✓".bright_green(), report.sent, queued);
+            if let Some(e) = report.blocked_by {
+                println!("{} {} still queued: {}", "⏳".yellow(), report.remaining, e);
+            }
+        }
+    }
+
+    let mut cache = cache::load_cache();
+    pull_occurrences(&api, &mut cache, false).await?;
+    pull_summary(&api, &mut cache).await?;
+    pull_plans(&api, &mut cache).await?;
+    cache::save_cache(&cache)?;
+    if output::is_machine() {
+        return output::print_record(&SyncRecord { sent, remaining, occurrences: cache.occurrences.len() });
+    }
+    println!("{} History up to date ({} occurrences)", "✓".bright_green(), cache.occurrences.len());
+
+    Ok(())
+}
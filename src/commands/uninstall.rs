@@ -1,5 +1,6 @@
 use std::{env, fs, io::{BufReader, BufRead, Write}, process::Command};
 use anyhow::Result;
+use crate::commands::remind;
 
 pub fn uninstall_command() -> Result<()> {
     if cfg!(windows) {
@@ -58,6 +59,16 @@ fn handle_uninstall_unix() {
         println!("Removed {}", imp_path);
     }
 
+    for unit in remind::installed_units() {
+        let _ = Command::new("systemctl")
+            .args(["--user", "disable", "--now"])
+            .arg(unit.file_name().unwrap())
+            .output();
+        if fs::remove_file(&unit).is_ok() {
+            println!("Removed {}", unit.display());
+        }
+    }
+
     remove_path_from_file(&format!("{}/.bashrc", home));
     remove_path_from_file(&format!("{}/.zshrc", home));
 
@@ -72,7 +83,7 @@ fn remove_path_from_file(file_path: &str) {
     let reader = BufReader::new(file);
     let lines: Vec<String> = reader
         .lines()
-        .filter_map(|line| line.ok())
+        .map_while(Result::ok)
         .filter(|line| !line.contains(".local/bin") || !line.contains("imp"))
         .collect();
 
@@ -1,70 +1,423 @@
-use anyhow::{Result, bail};
-use crate::auth::token_manager;
-use serde::Deserialize;
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Datelike, Days, Local, NaiveDate, Utc, Weekday};
+use colored::Colorize;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use crate::agenda;
+use crate::api::client::{ApiClient, OccurrenceQuery};
+use crate::api::types::{OccurrenceItem, OccurrencesResponse, PlanStatus, SummaryResponse};
+use crate::cache::{self, Cache};
+use crate::charts::{self, Style};
+use crate::commands::sync;
+use crate::error::{self, ImpError};
+use crate::parse::cadence::{plan_activity, plan_cadence};
+use crate::parse::log::parse_log;
+use crate::output::{self, OutputFormat};
+use crate::{queue, time};
 
-#[derive(Deserialize)]
-struct SummaryResponse {
-    summary: String,
+const HEATMAP_WEEKS: u32 = 26;
+const SPARKLINE_DAYS: u64 = 14;
+const TOP_ACTIVITIES: usize = 8;
+const WEEKLY_TOTALS: u64 = 8;
+
+/// Narrows `imp view occurrences` and `imp view stats`. Occurrences are asked
+/// for from the server with the filter; stats and offline views apply it to the cache.
+#[derive(Default)]
+pub struct OccurrenceFilter {
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub today: bool,
+    pub week: bool,
+    pub grep: Option<String>,
+    pub limit: Option<usize>,
 }
 
-#[derive(Deserialize)]
-struct OccurrenceItem {
-    timestamp: String,
-    text: String,
+impl OccurrenceFilter {
+    /// The server side of the filter. `--grep` matches anywhere in the text, which
+    /// the server can't do, so it's applied here afterwards, and the limit is only
+    /// sent without it so the server doesn't fill it with logs that don't match.
+    fn query(&self) -> Result<OccurrenceQuery> {
+        let (since, until) = self.days()?;
+        let start_of = |day: NaiveDate| {
+            day.and_hms_opt(0, 0, 0)
+                .and_then(|t| t.and_local_timezone(Local).earliest())
+                .map(|t| t.with_timezone(&Utc))
+        };
+        Ok(OccurrenceQuery {
+            since: since.and_then(start_of),
+            until: until.and_then(|day| day.succ_opt()).and_then(start_of),
+            limit: self.limit.filter(|_| self.grep.is_none()),
+            cursor: None,
+        })
+    }
+
+    fn is_set(&self) -> bool {
+        self.since.is_some() || self.until.is_some() || self.today || self.week || self.grep.is_some() || self.limit.is_some()
+    }
+
+    /// First and last local day to include.
+    fn days(&self) -> Result<(Option<NaiveDate>, Option<NaiveDate>)> {
+        let today = Local::now().date_naive();
+        if self.today {
+            return Ok((Some(today), Some(today)));
+        }
+        if self.week {
+            let monday = today - Days::new(u64::from(today.weekday().num_days_from_monday()));
+            return Ok((Some(monday), Some(today)));
+        }
+        Ok((
+            self.since.as_deref().map(time::parse_day).transpose()?,
+            self.until.as_deref().map(time::parse_day).transpose()?,
+        ))
+    }
 }
 
-#[derive(Deserialize)]
-struct OccurrencesResponse {
-    occurrences: Vec<OccurrenceItem>,
+/// An occurrence from the server or a log still waiting in the queue.
+struct Row {
+    time: DateTime<Local>,
+    text: String,
+    queued: bool,
 }
 
-pub async fn view_command(what: &str) -> Result<()> {
-    let token = token_manager::get_valid_token().await?;
-    let client = reqwest::Client::new();
+/// Shows history from the local cache, refreshing it first when it's stale
+/// (or always with `refresh`). Offline, stale data is shown instead of failing.
+pub async fn view_command(what: Option<&str>, refresh: bool, filter: OccurrenceFilter) -> Result<()> {
+    let occurrences = match what {
+        Some(what) => what == "occurrences" || what == "stats",
+        None => filter.is_set(),
+    };
+    if !occurrences && filter.is_set() {
+        bail!("Filters only apply to: imp view occurrences, imp view stats");
+    }
+    let stats = what == Some("stats");
+    if occurrences && !stats && filter.is_set() && !refresh {
+        let fetched = match fetch_filtered(&filter).await {
+            Ok(fetched) => fetched,
+            Err(e) if matches!(error::find(&e), Some(ImpError::NetworkUnavailable)) => {
+                let cache = cache::load_cache();
+                if cache.occurrences_synced_at.is_none() && cache.occurrences.is_empty() {
+                    return Err(e);
+                }
+                eprintln!("{}", "Offline, showing cached history".dimmed());
+                cache.occurrences
+            }
+            Err(e) => return Err(e),
+        };
+        return print_occurrences(rows(&fetched, &filter)?, filter.limit);
+    }
+    let mut cache = cache::load_cache();
 
-    match what {
-        "occurrences" => {
-            let response = client
-                .get("https://api.iepok.com/view/occurrences")
-                .bearer_auth(token)
-                .send()
-                .await?;
+    let (fresh, has_data) = if occurrences {
+        (
+            cache.occurrences_fresh() && (!stats || cache.plans_fresh()),
+            cache.occurrences_synced_at.is_some() || !cache.occurrences.is_empty(),
+        )
+    } else {
+        (cache.summary_fresh(), cache.summary.is_some())
+    };
 
-            if !response.status().is_success() {
-                bail!("Failed to get occurrences: {}", response.status());
+    if refresh || !fresh {
+        match update(&mut cache, occurrences, stats, refresh).await {
+            Ok(()) => cache::save_cache(&cache)?,
+            Err(e) if !refresh && has_data && matches!(error::find(&e), Some(ImpError::NetworkUnavailable)) => {
+                eprintln!("{}", "Offline, showing cached history".dimmed());
             }
+            Err(e) => return Err(e),
+        }
+    }
 
-            let data: OccurrencesResponse = response.json().await?;
-
-            if data.occurrences.is_empty() {
-                println!("No occurrences.");
-            } else {
-                for occ in data.occurrences {
-                    // Parse and format timestamp as HH:MM
-                    let time = if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(&occ.timestamp) {
-                        dt.format("%H:%M").to_string()
-                    } else {
-                        occ.timestamp
-                    };
-                    println!("{} - {}", time, occ.text);
-                }
+    if stats {
+        print_stats(&cache, &rows(&cache.occurrences, &filter)?, &filter)?;
+    } else if occurrences {
+        print_occurrences(rows(&cache.occurrences, &filter)?, filter.limit)?;
+    } else if output::is_machine() {
+        output::print_record(&SummaryResponse { summary: cache.summary.unwrap_or_default() })?;
+    } else {
+        println!("{}", cache.summary.as_deref().unwrap_or_default());
+    }
+
+    Ok(())
+}
+
+async fn update(cache: &mut Cache, occurrences: bool, plans: bool, full: bool) -> Result<()> {
+    let api = ApiClient::authenticated().await?;
+    if plans {
+        sync::pull_plans(&api, cache).await?;
+    }
+    if occurrences {
+        sync::pull_occurrences(&api, cache, full).await
+    } else {
+        sync::pull_summary(&api, cache).await
+    }
+}
+
+/// Just the occurrences the filter asks for, straight from the server.
+async fn fetch_filtered(filter: &OccurrenceFilter) -> Result<Vec<OccurrenceItem>> {
+    let query = filter.query()?;
+    let api = ApiClient::authenticated().await?;
+    let fetched = api.occurrences(&query)
+        .await
+        .map_err(ImpError::from)
+        .context("Failed to get occurrences")?;
+    Ok(fetched.occurrences)
+}
+
+/// Server (or cached) and queued occurrences that pass the filter, oldest first.
+fn rows(occurrences: &[OccurrenceItem], filter: &OccurrenceFilter) -> Result<Vec<Row>> {
+    let (since, until) = filter.days()?;
+    let grep = filter.grep.as_deref().map(str::to_lowercase);
+
+    let server = occurrences.iter().filter_map(|occ| {
+        Some(Row { time: occ.time()?.with_timezone(&Local), text: occ.text.clone(), queued: false })
+    });
+    // Logged offline and not on the server yet
+    let queued = queue::load_queue()?.into_iter().map(|entry| Row {
+        time: entry.occurred_at.unwrap_or(entry.captured_at).with_timezone(&Local),
+        text: entry.raw_input,
+        queued: true,
+    });
+
+    let mut rows: Vec<Row> = server
+        .chain(queued)
+        .filter(|row| since.is_none_or(|since| row.time.date_naive() >= since))
+        .filter(|row| until.is_none_or(|until| row.time.date_naive() <= until))
+        .filter(|row| grep.as_ref().is_none_or(|grep| row.text.to_lowercase().contains(grep)))
+        .collect();
+    rows.sort_by_key(|row| row.time);
+    Ok(rows)
+}
+
+fn print_occurrences(mut rows: Vec<Row>, limit: Option<usize>) -> Result<()> {
+    // Keep the most recent ones
+    if let Some(limit) = limit {
+        rows.drain(..rows.len().saturating_sub(limit));
+    }
+
+    if output::is_machine() {
+        let occurrences: Vec<OccurrenceItem> = rows
+            .into_iter()
+            .map(|row| OccurrenceItem { id: None, timestamp: row.time.to_rfc3339(), text: row.text, queued: row.queued })
+            .collect();
+        return match output::get() {
+            OutputFormat::Csv => output::print_records(&occurrences),
+            _ => output::print_record(&OccurrencesResponse { occurrences, deleted: Vec::new(), cursor: None }),
+        };
+    }
+
+    if rows.is_empty() {
+        println!("No occurrences.");
+        return Ok(());
+    }
+
+    let today = Local::now().date_naive();
+    let mut current_day = None;
+    for row in rows {
+        let day = row.time.date_naive();
+        if current_day != Some(day) {
+            if current_day.is_some() {
+                println!();
             }
+            println!("{}", day_label(day, today).bold());
+            current_day = Some(day);
         }
-        _ => {
-            let response = client
-                .get("https://api.iepok.com/view")
-                .bearer_auth(token)
-                .send()
-                .await?;
-
-            if !response.status().is_success() {
-                bail!("Failed to get view: {}", response.status());
-            }
 
-            let data: SummaryResponse = response.json().await?;
-            println!("{}", data.summary);
+        let queued = if row.queued { format!(" {}", "(queued)".dimmed()) } else { String::new() };
+        println!("  {} - {}{}", row.time.format("%H:%M"), row.text, queued);
+    }
+    Ok(())
+}
+
+/// Per-activity totals, keyed by the parsed activity (or the whole text).
+#[derive(Default)]
+struct ActivityStats {
+    /// Reps (or logs, when there's no count) per day.
+    amounts: BTreeMap<NaiveDate, u32>,
+    days: BTreeSet<NaiveDate>,
+}
+
+/// What `imp view stats --output json` prints; CSV gets just the activities.
+#[derive(Serialize)]
+struct StatsRecord {
+    daily: BTreeMap<NaiveDate, u32>,
+    activities: Vec<ActivityRecord>,
+    plans: Vec<PlanStatsRecord>,
+    weekly: Vec<WeekRecord>,
+}
+
+#[derive(Serialize)]
+struct ActivityRecord {
+    activity: String,
+    last_14_days: Vec<u32>,
+    /// Over everything shown, not just the last 14 days.
+    total: u32,
+    streak: u32,
+    best: u32,
+}
+
+/// How a cached plan is going, judged by its schedule rather than by calendar days.
+#[derive(Serialize)]
+struct PlanStatsRecord {
+    goal: String,
+    schedule: String,
+    this_week: u32,
+    weekly_target: u32,
+    /// In the schedule's own periods, see `agenda::plan_streaks`.
+    streak: u32,
+    best: u32,
+}
+
+#[derive(Serialize)]
+struct WeekRecord {
+    week_of: NaiveDate,
+    total: u32,
+}
+
+fn print_stats(cache: &Cache, rows: &[Row], filter: &OccurrenceFilter) -> Result<()> {
+    // Plan definitions are logged too, but aren't activity
+    let rows: Vec<&Row> = rows.iter().filter(|row| !row.text.contains("#plan")).collect();
+    if rows.is_empty() && !output::is_machine() {
+        println!("No occurrences.");
+        return Ok(());
+    }
+
+    let style = Style::detect();
+    let (since, until) = filter.days()?;
+    let end = until.unwrap_or_else(|| Local::now().date_naive());
+
+    let mut daily: BTreeMap<NaiveDate, u32> = BTreeMap::new();
+    let mut activities: HashMap<String, ActivityStats> = HashMap::new();
+    let mut logged: Vec<(NaiveDate, String, &str)> = Vec::new();
+    for row in &rows {
+        let day = row.time.date_naive();
+        *daily.entry(day).or_default() += 1;
+
+        let parsed = parse_log(&row.text);
+        let name = parsed
+            .as_ref()
+            .and_then(|p| p.activity.clone())
+            .unwrap_or_else(|| row.text.to_lowercase());
+        let amount = parsed
+            .and_then(|p| p.count.map(|count| count.saturating_mul(p.sets.unwrap_or(1))))
+            .unwrap_or(1);
+        logged.push((day, name.clone(), &row.text));
+
+        let stats = activities.entry(name).or_default();
+        let total = stats.amounts.entry(day).or_default();
+        *total = total.saturating_add(amount);
+        stats.days.insert(day);
+    }
+
+    // Busiest activities first
+    let recent: Vec<NaiveDate> = (0..SPARKLINE_DAYS).rev().map(|n| end - Days::new(n)).collect();
+    let mut ranked: Vec<ActivityRecord> = activities
+        .into_iter()
+        .map(|(activity, stats)| {
+            let last_14_days: Vec<u32> = recent.iter().map(|d| stats.amounts.get(d).copied().unwrap_or(0)).collect();
+            let (streak, best) = charts::streaks(&stats.days, end);
+            ActivityRecord { activity, total: stats.amounts.values().copied().fold(0, u32::saturating_add), last_14_days, streak, best }
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.total.cmp(&a.total).then_with(|| a.activity.cmp(&b.activity)));
+
+    let week_start = end.week(Weekday::Mon).first_day();
+    let plans: Vec<PlanStatsRecord> = cache
+        .plans
+        .iter()
+        .filter(|plan| plan.status != PlanStatus::Archived)
+        .filter_map(|plan| {
+            let cadence = plan_cadence(&plan.goal, plan.cadence.as_deref())?;
+            let activity = plan_activity(&plan.goal)?;
+            let done: BTreeSet<NaiveDate> = logged
+                .iter()
+                .filter(|(_, logged_activity, text)| agenda::counts_towards(&activity, logged_activity, text))
+                .map(|(day, ..)| *day)
+                .collect();
+            let (streak, best) = agenda::plan_streaks(&cadence.recurrence, &done, end);
+            Some(PlanStatsRecord {
+                goal: plan.goal.clone(),
+                schedule: cadence.recurrence.to_string(),
+                this_week: done.range(week_start..=end).count() as u32,
+                weekly_target: agenda::weekly_target(&cadence.recurrence),
+                streak,
+                best,
+            })
+        })
+        .collect();
+
+    // Logs per week, newest last
+    let end_monday = end - Days::new(u64::from(end.weekday().num_days_from_monday()));
+    let weekly: Vec<WeekRecord> = (0..WEEKLY_TOTALS)
+        .rev()
+        .map(|w| {
+            let monday = end_monday - Days::new(w * 7);
+            let sunday = monday + Days::new(6);
+            WeekRecord { week_of: monday, total: daily.range(monday..=sunday).map(|(_, c)| *c).sum() }
+        })
+        .collect();
+
+    match output::get() {
+        OutputFormat::Json => return output::print_record(&StatsRecord { daily, activities: ranked, plans, weekly }),
+        OutputFormat::Csv => return output::print_records(&ranked),
+        OutputFormat::Text => {}
+    }
+
+    let weeks = since
+        .map(|since| ((end - since).num_days() / 7 + 1).clamp(1, 52) as u32)
+        .unwrap_or(HEATMAP_WEEKS);
+    println!("{}", style.heading(&format!("Activity, last {} weeks", weeks)));
+    println!("{}\n", charts::heatmap(&daily, end, weeks, style));
+
+    let width = ranked.iter().take(TOP_ACTIVITIES).map(|a| a.activity.chars().count()).max().unwrap_or(0).max(8);
+    println!(
+        "{}",
+        style.heading(&format!("{:<width$}  {:<14}  {:>6}  {:>6}  {:>4}", "Activity", "Last 14 days", "Total", "Streak", "Best"))
+    );
+    for activity in ranked.iter().take(TOP_ACTIVITIES) {
+        println!(
+            "{:<width$}  {:<14}  {:>6}  {:>6}  {:>4}",
+            activity.activity,
+            charts::sparkline(&activity.last_14_days, style),
+            activity.total,
+            activity.streak,
+            activity.best,
+        );
+    }
+    println!();
+
+    if !plans.is_empty() {
+        let width = plans.iter().map(|p| p.goal.chars().count()).max().unwrap_or(0).clamp(4, 40);
+        println!("{}", style.heading(&format!("{:<width$}  {:>9}  {:>6}  {:>4}", "Plan", "This week", "Streak", "Best")));
+        for plan in &plans {
+            println!(
+                "{:<width$}  {:>9}  {:>6}  {:>4}",
+                plan.goal,
+                format!("{}/{}", plan.this_week, plan.weekly_target),
+                plan.streak,
+                plan.best,
+            );
         }
+        println!();
+    }
+
+    println!("{}", style.heading("Weekly totals"));
+    let max = weekly.iter().map(|w| w.total).max().unwrap_or(0);
+    for week in weekly {
+        println!(
+            "{}  {:>4}  {}",
+            style.dim(&week.week_of.format("%Y-%m-%d").to_string()),
+            week.total,
+            charts::bar(week.total, max, 30, style),
+        );
     }
 
     Ok(())
 }
+
+fn day_label(day: NaiveDate, today: NaiveDate) -> String {
+    let date = day.format("%a %Y-%m-%d");
+    match (today - day).num_days() {
+        0 => format!("Today, {}", date),
+        1 => format!("Yesterday, {}", date),
+        _ => date.to_string(),
+    }
+}
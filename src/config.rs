@@ -0,0 +1,283 @@
+use anyhow::{Context, Result, bail};
+use chrono::NaiveTime;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+pub const DEFAULT_PROFILE: &str = "default";
+pub const DEFAULT_API_URL: &str = "https://api.iepok.com";
+pub const DEFAULT_COGNITO_POOL: &str = "us-east-1_DAvkrVxUh";
+pub const DEFAULT_CLIENT_ID: &str = "6tlohqsfgoqiehi7q6027a3rl3";
+pub const DEFAULT_REFRESH_SKEW_SECS: i64 = 120;
+pub const DEFAULT_JWT_LEEWAY_SECS: u64 = 60;
+pub const DEFAULT_REMIND_AT: [&str; 2] = ["09:00", "19:00"];
+const TOKEN_STORES: [&str; 4] = ["auto", "keyring", "encrypted", "file"];
+
+/// Endpoint settings, either at the top level of `config.toml` or under `[profiles.<name>]`.
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ProfileConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cognito_pool: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    /// Cognito hosted UI domain, e.g. `https://example.auth.us-east-1.amazoncognito.com`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth_domain: Option<String>,
+    /// Where tokens are kept: `auto`, `keyring`, `encrypted` or `file`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_store: Option<String>,
+    /// Refresh access tokens this many seconds before they expire.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_skew_secs: Option<i64>,
+    /// Clock skew tolerated when checking token `exp`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jwt_leeway_secs: Option<u64>,
+    /// Overrides where signing keys come from; `file://` paths are read directly.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jwks_url: Option<String>,
+    /// Times of day `imp remind` checks for due plans, e.g. `["09:00", "19:00"]`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remind_at: Option<Vec<String>>,
+    /// No notifications in this range, e.g. `22:00-07:00`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quiet_hours: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct ConfigFile {
+    /// Profile used when neither `--profile` nor `IMP_PROFILE` is given.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active_profile: Option<String>,
+    #[serde(flatten)]
+    pub defaults: ProfileConfig,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, ProfileConfig>,
+}
+
+/// Settings resolved from built-in defaults, `config.toml`, the selected profile and env vars.
+pub struct Config {
+    pub profile: String,
+    pub api_url: String,
+    pub cognito_pool: String,
+    pub client_id: String,
+    /// Only set when the backend has a hosted login page for `imp login --browser`.
+    pub auth_domain: Option<String>,
+    pub token_store: String,
+    pub refresh_skew_secs: i64,
+    pub jwt_leeway_secs: u64,
+    pub jwks_url: String,
+    /// Sorted.
+    pub remind_at: Vec<NaiveTime>,
+    /// Start and end; the range wraps past midnight when start is later.
+    pub quiet_hours: Option<(NaiveTime, NaiveTime)>,
+}
+
+impl Config {
+    pub fn region(&self) -> &str {
+        pool_region(&self.cognito_pool)
+    }
+
+    /// The `iss` claim Cognito puts in tokens from this pool.
+    pub fn issuer(&self) -> String {
+        pool_issuer(&self.cognito_pool)
+    }
+
+    pub fn is_quiet(&self, time: NaiveTime) -> bool {
+        match self.quiet_hours {
+            Some((start, end)) if start <= end => time >= start && time < end,
+            Some((start, end)) => time >= start || time < end,
+            None => false,
+        }
+    }
+}
+
+/// Cognito pool ids are prefixed with their region, e.g. `us-east-1_abc`.
+fn pool_region(pool: &str) -> &str {
+    pool.split_once('_')
+        .map(|(region, _)| region)
+        .unwrap_or("us-east-1")
+}
+
+fn pool_issuer(pool: &str) -> String {
+    format!("https://cognito-idp.{}.amazonaws.com/{}", pool_region(pool), pool)
+}
+
+fn default_jwks_url(pool: &str) -> String {
+    format!("{}/.well-known/jwks.json", pool_issuer(pool))
+}
+
+fn default_remind_at() -> Vec<NaiveTime> {
+    DEFAULT_REMIND_AT.iter().filter_map(|t| parse_clock(t)).collect()
+}
+
+fn parse_clock(time: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(time.trim(), "%H:%M").ok()
+}
+
+fn parse_remind_at(times: &[String]) -> Result<Vec<NaiveTime>> {
+    let mut parsed = times
+        .iter()
+        .map(|t| parse_clock(t).with_context(|| format!("remind_at: \"{}\" isn't a time like 18:30", t)))
+        .collect::<Result<Vec<_>>>()?;
+    parsed.sort();
+    parsed.dedup();
+    Ok(parsed)
+}
+
+fn parse_quiet_hours(range: &str) -> Result<(NaiveTime, NaiveTime)> {
+    range
+        .split_once('-')
+        .and_then(|(start, end)| Some((parse_clock(start)?, parse_clock(end)?)))
+        .with_context(|| format!("quiet_hours: \"{}\" isn't a range like 22:00-07:00", range))
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+pub fn imp_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap()
+        .join("imp")
+}
+
+fn config_path() -> PathBuf {
+    imp_dir().join("config.toml")
+}
+
+/// Where a profile keeps its tokens and queue. The default profile uses the
+/// top-level directory so installs from before profiles existed keep working.
+pub fn profile_dir_for(profile: &str) -> PathBuf {
+    if profile == DEFAULT_PROFILE {
+        imp_dir()
+    } else {
+        imp_dir().join("profiles").join(profile)
+    }
+}
+
+pub fn profile_dir() -> PathBuf {
+    profile_dir_for(&get().profile)
+}
+
+pub fn load_config_file() -> Result<ConfigFile> {
+    let path = config_path();
+    if !path.exists() {
+        return Ok(ConfigFile::default());
+    }
+    let text = fs::read_to_string(&path).context("Failed to read config.toml")?;
+    toml::from_str(&text).with_context(|| format!("Invalid config file {}", path.display()))
+}
+
+pub fn save_config_file(file: &ConfigFile) -> Result<()> {
+    let path = config_path();
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(&path, toml::to_string_pretty(file)?).context("Failed to write config.toml")?;
+    Ok(())
+}
+
+fn resolve(profile: Option<&str>) -> Result<Config> {
+    let file = load_config_file()?;
+    // A stale active_profile shouldn't lock the user out of `imp profile switch`
+    let profile = profile
+        .map(str::to_string)
+        .or_else(|| env::var("IMP_PROFILE").ok())
+        .or_else(|| file.active_profile.clone().filter(|p| file.profiles.contains_key(p)))
+        .unwrap_or_else(|| DEFAULT_PROFILE.to_string());
+
+    let section = match file.profiles.get(&profile) {
+        Some(section) => section.clone(),
+        None if profile == DEFAULT_PROFILE => ProfileConfig::default(),
+        None => bail!("Profile '{}' not found in {}", profile, config_path().display()),
+    };
+
+    let pick = |var: &str, from_profile: &Option<String>, from_file: &Option<String>, default: &str| {
+        env::var(var)
+            .ok()
+            .filter(|v| !v.is_empty())
+            .or_else(|| from_profile.clone())
+            .or_else(|| from_file.clone())
+            .unwrap_or_else(|| default.to_string())
+    };
+
+    let token_store = pick("IMP_TOKEN_STORE", &section.token_store, &file.defaults.token_store, "auto");
+    if !TOKEN_STORES.contains(&token_store.as_str()) {
+        bail!("Unknown token_store '{}', expected one of: {}", token_store, TOKEN_STORES.join(", "));
+    }
+
+    let refresh_skew_secs = match env::var("IMP_REFRESH_SKEW_SECS") {
+        Ok(v) => v.parse().context("IMP_REFRESH_SKEW_SECS must be a number of seconds")?,
+        Err(_) => section.refresh_skew_secs
+            .or(file.defaults.refresh_skew_secs)
+            .unwrap_or(DEFAULT_REFRESH_SKEW_SECS),
+    };
+
+    let jwt_leeway_secs = match env::var("IMP_JWT_LEEWAY_SECS") {
+        Ok(v) => v.parse().context("IMP_JWT_LEEWAY_SECS must be a number of seconds")?,
+        Err(_) => section.jwt_leeway_secs
+            .or(file.defaults.jwt_leeway_secs)
+            .unwrap_or(DEFAULT_JWT_LEEWAY_SECS),
+    };
+
+    let remind_at = match env::var("IMP_REMIND_AT") {
+        Ok(v) => parse_remind_at(&v.split(',').map(str::to_string).collect::<Vec<_>>())?,
+        Err(_) => match section.remind_at.as_ref().or(file.defaults.remind_at.as_ref()) {
+            Some(times) => parse_remind_at(times)?,
+            None => default_remind_at(),
+        },
+    };
+    let quiet_hours = Some(pick("IMP_QUIET_HOURS", &section.quiet_hours, &file.defaults.quiet_hours, ""))
+        .filter(|q| !q.is_empty())
+        .map(|q| parse_quiet_hours(&q))
+        .transpose()?;
+
+    let cognito_pool = pick("IMP_COGNITO_POOL", &section.cognito_pool, &file.defaults.cognito_pool, DEFAULT_COGNITO_POOL);
+    let jwks_url = pick("IMP_JWKS_URL", &section.jwks_url, &file.defaults.jwks_url, &default_jwks_url(&cognito_pool));
+
+    Ok(Config {
+        api_url: pick("IMP_API_URL", &section.api_url, &file.defaults.api_url, DEFAULT_API_URL),
+        cognito_pool,
+        client_id: pick("IMP_CLIENT_ID", &section.client_id, &file.defaults.client_id, DEFAULT_CLIENT_ID),
+        auth_domain: Some(pick("IMP_AUTH_DOMAIN", &section.auth_domain, &file.defaults.auth_domain, ""))
+            .filter(|d| !d.is_empty()),
+        token_store,
+        refresh_skew_secs,
+        jwt_leeway_secs,
+        jwks_url,
+        remind_at,
+        quiet_hours,
+        profile,
+    })
+}
+
+/// Settings for a profile other than the active one, e.g. to find where its tokens are kept.
+pub fn for_profile(profile: &str) -> Result<Config> {
+    resolve(Some(profile))
+}
+
+/// Resolves the configuration once at startup; `profile` comes from the `--profile` flag.
+pub fn init(profile: Option<&str>) -> Result<()> {
+    let config = resolve(profile)?;
+    let _ = CONFIG.set(config);
+    Ok(())
+}
+
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(|| {
+        resolve(None).unwrap_or_else(|_| Config {
+            jwks_url: default_jwks_url(DEFAULT_COGNITO_POOL),
+            profile: DEFAULT_PROFILE.to_string(),
+            api_url: DEFAULT_API_URL.to_string(),
+            cognito_pool: DEFAULT_COGNITO_POOL.to_string(),
+            client_id: DEFAULT_CLIENT_ID.to_string(),
+            auth_domain: None,
+            token_store: "auto".to_string(),
+            refresh_skew_secs: DEFAULT_REFRESH_SKEW_SECS,
+            jwt_leeway_secs: DEFAULT_JWT_LEEWAY_SECS,
+            remind_at: default_remind_at(),
+            quiet_hours: None,
+        })
+    })
+}
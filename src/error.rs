@@ -0,0 +1,92 @@
+//! Failures the user can do something about. Each one carries a hint and
+//! its own exit code so scripts can tell "offline" apart from "signed out".
+
+use std::fmt;
+use reqwest::StatusCode;
+use crate::api::client::ApiError;
+
+#[derive(Debug)]
+pub enum ImpError {
+    NotLoggedIn,
+    NetworkUnavailable,
+    ServerRejected(u16, String),
+    InvalidOtp,
+    ExpiredOtp,
+    RateLimited,
+    UserNotConfirmed,
+    /// The server answered, but not in a shape this version understands.
+    BadResponse(String),
+}
+
+impl ImpError {
+    /// 1 is any other failure and 2 is a usage error from clap.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ImpError::NotLoggedIn => 3,
+            ImpError::NetworkUnavailable => 4,
+            ImpError::ServerRejected(..) => 5,
+            ImpError::InvalidOtp => 6,
+            ImpError::ExpiredOtp => 7,
+            ImpError::RateLimited => 8,
+            ImpError::UserNotConfirmed => 9,
+            ImpError::BadResponse(_) => 10,
+        }
+    }
+
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            ImpError::NotLoggedIn => Some("Run: imp login"),
+            ImpError::NetworkUnavailable => {
+                Some("Check your connection. Logs made offline are queued and sent by: imp sync")
+            }
+            ImpError::ServerRejected(status, _) if *status >= 500 => {
+                Some("The server had a problem. Try again in a moment")
+            }
+            ImpError::ServerRejected(..) => None,
+            ImpError::InvalidOtp => Some("Check the code in your email, or run imp login to get a new one"),
+            ImpError::ExpiredOtp => Some("Codes are only valid for a few minutes. Run imp login to get a new one"),
+            ImpError::RateLimited => Some("Wait a few minutes before trying again"),
+            ImpError::UserNotConfirmed => Some("Run imp login and enter the code from your email to confirm your account"),
+            ImpError::BadResponse(_) => Some("This version of imp may be too old for the server. Run: imp update"),
+        }
+    }
+}
+
+impl fmt::Display for ImpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImpError::NotLoggedIn => write!(f, "Not logged in"),
+            ImpError::NetworkUnavailable => write!(f, "Can't reach the server"),
+            ImpError::ServerRejected(status, body) if body.is_empty() => {
+                write!(f, "Server rejected the request ({})", status)
+            }
+            ImpError::ServerRejected(status, body) => {
+                write!(f, "Server rejected the request ({}): {}", status, body)
+            }
+            ImpError::InvalidOtp => write!(f, "That code isn't right"),
+            ImpError::ExpiredOtp => write!(f, "That code has expired"),
+            ImpError::RateLimited => write!(f, "Too many attempts"),
+            ImpError::UserNotConfirmed => write!(f, "Your account isn't confirmed yet"),
+            ImpError::BadResponse(detail) => write!(f, "Couldn't read the server's response: {}", detail),
+        }
+    }
+}
+
+impl std::error::Error for ImpError {}
+
+impl From<ApiError> for ImpError {
+    fn from(e: ApiError) -> Self {
+        match e {
+            ApiError::Network(_) => ImpError::NetworkUnavailable,
+            ApiError::Decode(e) => ImpError::BadResponse(e.to_string()),
+            ApiError::Status { status: StatusCode::UNAUTHORIZED, .. } => ImpError::NotLoggedIn,
+            ApiError::Status { status: StatusCode::TOO_MANY_REQUESTS, .. } => ImpError::RateLimited,
+            ApiError::Status { status, body } => ImpError::ServerRejected(status.as_u16(), body),
+        }
+    }
+}
+
+/// Finds the actionable error behind `e`, however much context was added on top.
+pub fn find(e: &anyhow::Error) -> Option<&ImpError> {
+    e.chain().find_map(|cause| cause.downcast_ref::<ImpError>())
+}
@@ -1,32 +1,95 @@
+mod agenda;
+mod api;
 mod commands;
 mod auth;
+mod cache;
+mod charts;
+mod config;
+mod error;
+mod output;
+mod parse;
+mod queue;
+mod state;
+#[cfg(test)]
+mod test_support;
+mod time;
 
 use std::env;
-use clap::error::ErrorKind;
+use clap::error::{ContextKind, ErrorKind};
 use clap::{Parser, Subcommand};
+use colored::Colorize;
 
+use crate::commands::analyze::analyze_command;
 use crate::commands::confirm::confirm_command;
-use crate::commands::devices::devices_command;
+use crate::commands::devices::{devices_command, remove_device_command};
 use crate::commands::log::log_command;
 use crate::commands::login::login_command;
 use crate::commands::logout::logout_command;
-use crate::commands::passkey::remove_passkey_command;
-use crate::commands::plan::plan_command;
+use crate::commands::passkey::{
+    passkey_list_command, passkey_register_command, passkey_remove_command,
+};
+use crate::commands::plan::{
+    plan_archive_command, plan_command, plan_edit_command, plan_list_command, plan_pause_command,
+    plan_resume_command, plan_show_command,
+};
+use crate::commands::profile::{
+    profile_add_command, profile_list_command, profile_remove_command, profile_switch_command,
+};
+use crate::commands::prompt::{PromptTarget, prompt_command};
+use crate::commands::remind::remind_command;
+use crate::commands::status::status_command;
+use crate::commands::sync::{flush_queue, sync_command};
 use crate::commands::uninstall::uninstall_command;
 use crate::commands::update::update_command;
-use crate::commands::view::view_command;
+use crate::commands::view::{OccurrenceFilter, view_command};
+use crate::config::ProfileConfig;
+use crate::output::OutputFormat;
 
 #[derive(Parser, Debug)]
 #[command(name = "imp", about = "Simple CLI tool", version)]
 struct Args {
+    /// Use this profile for one command instead of the active one
+    #[arg(long, global = true)]
+    profile: Option<String>,
+
+    /// Print results as text (default), json or csv; machine formats have no colors or emoji
+    #[arg(long, global = true, value_enum, default_value_t)]
+    output: OutputFormat,
+
     #[command(subcommand)]
     command: Commands,
 }
 
 #[derive(Subcommand, Debug)]
 enum Commands {
+    /// Log an action (also the default: `imp 5 pushups`)
+    Log {
+        /// What you did, e.g. 5 pushups. Use - to read one log per line from stdin
+        #[arg(required = true, num_args = 1..)]
+        words: Vec<String>,
+        /// When it happened: 07:30, 7pm, "yesterday 18:00", "monday 7am", "2h ago" or ISO 8601
+        #[arg(long)]
+        at: Option<String>,
+        /// It happened yesterday (at the same time, or at --at)
+        #[arg(long)]
+        yesterday: bool,
+        /// Add a tag; repeat for several
+        #[arg(long)]
+        tag: Vec<String>,
+        /// Free-form note kept with the log
+        #[arg(long)]
+        note: Option<String>,
+    },
+
     /// Login via browser (magic link or passkey)
-    Login,
+    Login {
+        /// Sign in with a passkey stored on this machine
+        #[arg(long, conflicts_with = "browser")]
+        passkey: bool,
+        /// Sign in through the login page in your browser
+        #[arg(long)]
+        browser: bool,
+    },
 
     /// Sign out from this device
     Logout {
@@ -36,30 +99,125 @@ enum Commands {
     },
 
     /// List devices where you're signed in
-    Devices,
+    Devices {
+        /// Print as JSON (same as --output json)
+        #[arg(long)]
+        json: bool,
+    },
 
-    /// Remove selected device
+    /// Forget a device, and optionally sign out everywhere to end its sessions
     Remove {
+        /// Device key as shown by `imp devices`
         device_id: String,
+        /// Allow removing the device you're using right now
+        #[arg(long)]
+        force: bool,
+        /// Also sign out everywhere, here included, since a forgotten device stays signed in
+        #[arg(long)]
+        logout_all: bool,
+        /// Don't ask for confirmation
+        #[arg(long, short)]
+        yes: bool,
     },
 
     /// Check login status
-    Status,
+    Status {
+        /// Print as JSON for scripts and shell prompts (same as --output json)
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Manage passkeys for signing in without email codes
+    Passkey {
+        #[command(subcommand)]
+        action: PasskeyAction,
+    },
+
+    /// Manage accounts and backends (work, personal, staging, ...)
+    Profile {
+        #[command(subcommand)]
+        action: ProfileAction,
+    },
     
-    /// Make a new plan
+    /// Make a new plan, or manage existing ones
+    #[command(args_conflicts_with_subcommands = true)]
     Plan {
-        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        #[command(subcommand)]
+        action: Option<PlanAction>,
+        /// Don't ask to confirm the schedule
+        #[arg(long, short)]
+        yes: bool,
+        /// The goal, e.g. "10 pullups every day" or "run 3x per week until June"
         goal: Vec<String>,
     },
     
     /// Confirm your implementations
     Confirm,
+
+    /// Get suggestions based on your actions: plan adherence, missed days, trends and streaks
+    Analyze {
+        /// How far back to look, e.g. 2w, 30d, 3 months, year
+        #[arg(long, default_value = "4w")]
+        period: String,
+        /// Fetch everything from the server instead of using the local cache
+        #[arg(long)]
+        refresh: bool,
+    },
+
+    /// Notify on the desktop when plans are due; runs until stopped
+    Remind {
+        /// Print the plans due today and exit with how many (3 or more are offset by 8 to skip the error codes)
+        #[arg(long, conflicts_with_all = ["install", "snooze"])]
+        check: bool,
+        /// Write a systemd user unit that runs reminders in the background
+        #[arg(long, conflicts_with = "snooze")]
+        install: bool,
+        /// Stay quiet for this long, e.g. 30m, 2h, 1d, or "off" to resume
+        #[arg(long)]
+        snooze: Option<String>,
+    },
+
+    /// Print today's progress for a shell prompt or status bar, from local data only
+    Prompt {
+        /// Color it for this shell or bar
+        #[arg(long = "for", value_enum, default_value_t)]
+        target: PromptTarget,
+        /// Template with {progress} {check} {fire} {done} {total} {due} {streak} {queued}
+        #[arg(long)]
+        format: Option<String>,
+        /// Update the cache and exit; started in the background when it's stale
+        #[arg(long, hide = true)]
+        refresh: bool,
+    },
+
+    /// Send logs that were queued while offline and update local history
+    Sync,
     
     /// View your history
     View {
-        /// What to view: summary (default), occurrences
-        #[arg(default_value = "summary")]
-        what: String,
+        /// What to view: summary (default, or occurrences when filtering), occurrences, stats
+        what: Option<String>,
+        /// Fetch everything from the server instead of using the local cache
+        #[arg(long)]
+        refresh: bool,
+        /// Only occurrences on or after this day, e.g. 2024-05-01, monday, "2 weeks ago"
+        #[arg(long)]
+        since: Option<String>,
+        /// Only occurrences on or before this day
+        #[arg(long)]
+        until: Option<String>,
+        /// Only today's occurrences
+        #[arg(long, conflicts_with_all = ["since", "until", "week"])]
+        today: bool,
+        /// Only this week's occurrences, from Monday
+        #[arg(long, conflicts_with_all = ["since", "until"])]
+        week: bool,
+        /// Only occurrences containing this text (case-insensitive)
+        #[arg(long)]
+        grep: Option<String>,
+        /// Show at most this many of the most recent occurrences
+        #[arg(long)]
+        limit: Option<usize>,
     },
     
     /// Update imp
@@ -69,36 +227,277 @@ enum Commands {
     Uninstall,
 }
 
+#[derive(Subcommand, Debug)]
+enum PasskeyAction {
+    /// Create a passkey on this machine and register it with your account
+    Register,
+
+    /// List passkeys registered for your account
+    List,
+
+    /// Remove a passkey from your account
+    Remove {
+        /// Credential id as shown by `imp passkey list`
+        credential_id: String,
+        /// Don't ask for confirmation
+        #[arg(long, short)]
+        yes: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum PlanAction {
+    /// List active and paused plans with their cadence and progress
+    List {
+        /// Include archived plans
+        #[arg(long)]
+        all: bool,
+    },
+
+    /// Show a plan and everything logged towards it
+    Show {
+        /// Plan id as shown by `imp plan list`; a unique prefix is enough
+        id: String,
+    },
+
+    /// Change a plan's goal or target
+    Edit {
+        id: String,
+        /// New goal text
+        #[arg(long)]
+        goal: Option<String>,
+        /// New target, e.g. 10 for "10 pullups"
+        #[arg(long)]
+        target: Option<u32>,
+    },
+
+    /// Pause a plan so missed days don't count, e.g. while travelling
+    Pause {
+        id: String,
+        /// First paused day (default today)
+        #[arg(long)]
+        from: Option<String>,
+        /// Last paused day (default until resumed)
+        #[arg(long)]
+        until: Option<String>,
+    },
+
+    /// Resume a paused plan
+    Resume {
+        id: String,
+    },
+
+    /// Retire a plan; it stays in `imp plan list --all`
+    Archive {
+        id: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ProfileAction {
+    /// List profiles, marking the active one
+    List,
+
+    /// Create a new profile
+    Add {
+        name: String,
+        /// API base URL (defaults to the hosted backend)
+        #[arg(long)]
+        api_url: Option<String>,
+        /// Cognito user pool id, e.g. us-east-1_XXXXXXXXX
+        #[arg(long)]
+        cognito_pool: Option<String>,
+        /// Cognito app client id
+        #[arg(long)]
+        client_id: Option<String>,
+        /// Hosted login domain for `imp login --browser`
+        #[arg(long)]
+        auth_domain: Option<String>,
+    },
+
+    /// Make a profile the active one
+    Switch {
+        name: String,
+    },
+
+    /// Delete a profile and its stored tokens
+    Remove {
+        name: String,
+        /// Remove it even if it has logs that haven't been sent
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+/// Prints the error with its hint, if it has one, and exits with its code.
+fn exit_with_error(e: anyhow::Error) -> ! {
+    eprintln!("Error: {:#}", e);
+    let error = error::find(&e);
+    if let Some(hint) = error.and_then(|e| e.hint()) {
+        eprintln!("{} {}", "Hint:".dimmed(), hint);
+    }
+    std::process::exit(error.map_or(1, |e| e.exit_code()));
+}
+
+/// Parses the command line. Anything that isn't a command is something to log,
+/// so `imp 5 pushups` means `imp log 5 pushups`. Only a lone word that's close
+/// to a command, like `imp stauts`, is taken for a typo.
+fn parse_args(args: &[String]) -> Args {
+    try_parse_args(args).unwrap_or_else(|err| err.exit())
+}
+
+fn try_parse_args(args: &[String]) -> Result<Args, clap::Error> {
+    let err = match Args::try_parse_from(args) {
+        Ok(parsed) => return Ok(parsed),
+        Err(err) if err.kind() == ErrorKind::InvalidSubcommand => err,
+        Err(err) => return Err(err),
+    };
+
+    // Where the unknown command is, after any global options
+    let Some(at) = err
+        .get(ContextKind::InvalidSubcommand)
+        .map(|word| word.to_string())
+        .and_then(|word| args.iter().skip(1).position(|arg| *arg == word))
+        .map(|at| at + 1)
+    else {
+        return Err(err);
+    };
+    let lone_word = at + 1 == args.len();
+    if lone_word && err.get(ContextKind::SuggestedSubcommand).is_some() {
+        return Err(err);
+    }
+
+    let mut with_log = args.to_vec();
+    with_log.insert(at, "log".to_string());
+    Args::try_parse_from(with_log).map_err(|_| err)
+}
+
 #[tokio::main]
 async fn main() {
     let args: Vec<_> = env::args().collect();
-    match Args::try_parse_from(&args) {
-        Ok(parsed_args) => {
-            let result = match parsed_args.command {
-                Commands::Login => login_command().await,
-                Commands::Logout { all } => logout_command(all).await,
-                Commands::Remove { device_id } => remove_passkey_command(&device_id),
-                Commands::Devices => devices_command(),
-                Commands::Status => Ok(()),
-                Commands::Plan { goal } => plan_command(goal).await,
-                Commands::Confirm => confirm_command().await,
-                Commands::View { what } => view_command(&what).await,
-                Commands::Update => update_command().await,
-                Commands::Uninstall => uninstall_command(),
-            };
-            if let Err(e) = result {
-                eprintln!("Error: {}", e);
-                std::process::exit(1);
-            }
+    let parsed_args = parse_args(&args);
+
+    output::init(match parsed_args.command {
+        Commands::Status { json: true } | Commands::Devices { json: true } => OutputFormat::Json,
+        _ => parsed_args.output,
+    });
+    if let Err(e) = config::init(parsed_args.profile.as_deref()) {
+        exit_with_error(e);
+    }
+    let replay = !matches!(
+        parsed_args.command,
+        Commands::Sync
+            | Commands::Status { .. }
+            | Commands::Logout { .. }
+            | Commands::Profile { .. }
+            | Commands::Update
+            | Commands::Uninstall
+            | Commands::Remind { .. }
+            | Commands::Prompt { .. }
+    );
+    let result = match parsed_args.command {
+        Commands::Log { words, at, yesterday, tag, note } => {
+            log_command(words, at, yesterday, tag, note).await
         }
-        Err(err) => {
-            if err.kind() == ErrorKind::InvalidSubcommand
-              && args.len() > 1
-              && log_command(&args[1..]).await.is_ok() {
-                return;
+        Commands::Login { passkey, browser } => login_command(passkey, browser).await,
+        Commands::Logout { all } => logout_command(all).await,
+        Commands::Remove { device_id, force, logout_all, yes } => {
+            remove_device_command(&device_id, force, logout_all, yes).await
+        }
+        Commands::Devices { .. } => devices_command().await,
+        Commands::Status { .. } => status_command(),
+        Commands::Passkey { action } => match action {
+            PasskeyAction::Register => passkey_register_command().await,
+            PasskeyAction::List => passkey_list_command().await,
+            PasskeyAction::Remove { credential_id, yes } => {
+                passkey_remove_command(&credential_id, yes).await
+            }
+        },
+        Commands::Profile { action } => match action {
+            ProfileAction::List => profile_list_command(),
+            ProfileAction::Add { name, api_url, cognito_pool, client_id, auth_domain } => {
+                let profile = ProfileConfig { api_url, cognito_pool, client_id, auth_domain, ..Default::default() };
+                profile_add_command(&name, profile)
             }
-            err.exit();
+            ProfileAction::Switch { name } => profile_switch_command(&name),
+            ProfileAction::Remove { name, force } => profile_remove_command(&name, force),
+        },
+        Commands::Plan { action, yes, goal } => match action {
+            None => plan_command(goal, yes).await,
+            Some(PlanAction::List { all }) => plan_list_command(all).await,
+            Some(PlanAction::Show { id }) => plan_show_command(&id).await,
+            Some(PlanAction::Edit { id, goal, target }) => plan_edit_command(&id, goal, target).await,
+            Some(PlanAction::Pause { id, from, until }) => plan_pause_command(&id, from, until).await,
+            Some(PlanAction::Resume { id }) => plan_resume_command(&id).await,
+            Some(PlanAction::Archive { id }) => plan_archive_command(&id).await,
+        },
+        Commands::Confirm => confirm_command().await,
+        Commands::Analyze { period, refresh } => analyze_command(&period, refresh).await,
+        Commands::Remind { check, install, snooze } => remind_command(check, install, snooze).await,
+        Commands::Prompt { target, format, refresh } => prompt_command(target, format, refresh).await,
+        Commands::Sync => sync_command().await,
+        Commands::View { what, refresh, since, until, today, week, grep, limit } => {
+            let filter = OccurrenceFilter { since, until, today, week, grep, limit };
+            view_command(what.as_deref(), refresh, filter).await
         }
+        Commands::Update => update_command().await,
+        Commands::Uninstall => uninstall_command(),
+    };
+    if let Err(e) = result {
+        exit_with_error(e);
+    }
+    if replay {
+        flush_queue().await;
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Args {
+        parse_args(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn near_miss_commands_with_more_words_are_logs() {
+        for (args, logged) in [
+            (["imp", "plank", "60s"], ["plank", "60s"]),
+            (["imp", "remand", "3km"], ["remand", "3km"]),
+            (["imp", "logs", "run"], ["logs", "run"]),
+        ] {
+            let Commands::Log { words, .. } = parse(&args).command else {
+                panic!("not a log: {:?}", args);
+            };
+            assert_eq!(words, logged);
+        }
+    }
+
+    #[test]
+    fn lone_near_misses_are_typos() {
+        let args: Vec<String> = ["imp", "stauts"].iter().map(|arg| arg.to_string()).collect();
+        assert!(try_parse_args(&args).is_err());
+        let Commands::Log { words, .. } = parse(&["imp", "swimming"]).command else {
+            panic!("not a log");
+        };
+        assert_eq!(words, ["swimming"]);
+    }
+
+    #[test]
+    fn logs_follow_global_options() {
+        let parsed = parse(&["imp", "--profile", "work", "5", "pushups"]);
+        assert_eq!(parsed.profile.as_deref(), Some("work"));
+        assert!(matches!(parsed.command, Commands::Log { .. }));
+    }
+
+    #[test]
+    fn plan_flags_go_anywhere() {
+        for args in [["imp", "plan", "-y", "run", "daily"], ["imp", "plan", "run", "daily", "-y"]] {
+            let Commands::Plan { yes, goal, .. } = parse(&args).command else {
+                panic!("not a plan: {:?}", args);
+            };
+            assert!(yes);
+            assert_eq!(goal, ["run", "daily"]);
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! How results are printed: for people (colors, emoji) or for scripts (JSON, CSV).
+//! Machine formats never contain colors, emoji or progress messages.
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt::Display;
+use std::io::{self, Write};
+use std::sync::OnceLock;
+
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+    Csv,
+}
+
+static FORMAT: OnceLock<OutputFormat> = OnceLock::new();
+
+pub fn init(format: OutputFormat) {
+    if format != OutputFormat::Text {
+        colored::control::set_override(false);
+    }
+    let _ = FORMAT.set(format);
+}
+
+pub fn get() -> OutputFormat {
+    FORMAT.get().copied().unwrap_or_default()
+}
+
+/// Whether output is for a script rather than a person.
+pub fn is_machine() -> bool {
+    get() != OutputFormat::Text
+}
+
+/// Prints a message that only a person wants, like "✓ Removed". Scripts get
+/// a record, or nothing and the exit code.
+pub fn say(message: impl Display) {
+    if !is_machine() {
+        println!("{}", message);
+    }
+}
+
+/// Prints progress of an interactive command. With machine output it goes to
+/// stderr, so the person running it still sees it and stdout stays parseable.
+pub fn progress(message: impl Display) {
+    if is_machine() {
+        eprintln!("{}", message);
+    } else {
+        println!("{}", message);
+    }
+}
+
+/// Asks a question, on stderr with machine output, and returns the trimmed answer.
+pub fn ask(question: impl Display) -> Result<String> {
+    if is_machine() {
+        eprint!("{} ", question);
+        io::stderr().flush()?;
+    } else {
+        print!("{} ", question);
+        io::stdout().flush()?;
+    }
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(answer.trim().to_string())
+}
+
+/// Asks a yes/no question that defaults to no.
+pub fn confirm(question: impl Display) -> Result<bool> {
+    let answer = ask(format!("{} [y/N]", question))?;
+    Ok(matches!(answer.to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Asks a yes/no question that defaults to yes.
+pub fn confirm_default_yes(question: impl Display) -> Result<bool> {
+    let answer = ask(format!("{} [Y/n]", question))?;
+    Ok(matches!(answer.to_lowercase().as_str(), "" | "y" | "yes"))
+}
+
+/// Prints a single record: a JSON object, or a CSV header and one row.
+pub fn print_record<T: Serialize>(record: &T) -> Result<()> {
+    match get() {
+        OutputFormat::Csv => print_csv(&[serde_json::to_value(record)?]),
+        _ => print_json(record),
+    }
+}
+
+/// Prints a list of records: a JSON array, or CSV with one row per record.
+pub fn print_records<T: Serialize>(records: &[T]) -> Result<()> {
+    match get() {
+        OutputFormat::Csv => {
+            let rows = records.iter().map(serde_json::to_value).collect::<Result<Vec<_>, _>>()?;
+            print_csv(&rows)
+        }
+        _ => print_json(records),
+    }
+}
+
+fn print_json<T: Serialize + ?Sized>(value: &T) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+/// Columns come from the first row's fields; nested values are written as JSON.
+fn print_csv(rows: &[Value]) -> Result<()> {
+    let Some(Value::Object(first)) = rows.first() else {
+        return Ok(());
+    };
+    let columns: Vec<&String> = first.keys().collect();
+
+    println!("{}", columns.iter().map(|c| csv_field(c)).collect::<Vec<_>>().join(","));
+    for row in rows {
+        let fields: Vec<String> = columns
+            .iter()
+            .map(|column| match row.get(column.as_str()) {
+                None | Some(Value::Null) => String::new(),
+                Some(Value::String(s)) => csv_field(s),
+                Some(other) => csv_field(&other.to_string()),
+            })
+            .collect();
+        println!("{}", fields.join(","));
+    }
+    Ok(())
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
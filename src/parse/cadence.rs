@@ -0,0 +1,478 @@
+//! Reads the schedule out of a plan like "10 pullups every day" or
+//! "run 3x per week until June" so it can be confirmed before it's sent.
+
+use anyhow::{Context, Result};
+use chrono::{Datelike, Days, Local, Month, Months, NaiveDate, Weekday};
+use serde::Serialize;
+use std::fmt;
+use crate::parse::log::parse_log;
+use crate::time;
+
+const WEEKDAYS: [Weekday; 5] = [Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri];
+const WEEKEND: [Weekday; 2] = [Weekday::Sat, Weekday::Sun];
+
+/// How often a plan is meant to happen.
+#[derive(Serialize, Clone, PartialEq, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Recurrence {
+    Daily,
+    PerWeek { times: u32 },
+    /// On these days of the week, Monday first.
+    OnDays { days: Vec<Weekday> },
+    EveryNDays { days: u32 },
+}
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct Cadence {
+    #[serde(flatten)]
+    pub recurrence: Recurrence,
+    /// Last day of the plan, inclusive.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub until: Option<NaiveDate>,
+    /// How many each time, e.g. 10 for "10 pullups every day".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<u32>,
+}
+
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Recurrence::Daily => write!(f, "every day"),
+            Recurrence::PerWeek { times: 1 } => write!(f, "once a week"),
+            Recurrence::PerWeek { times: 2 } => write!(f, "twice a week"),
+            Recurrence::PerWeek { times } => write!(f, "{} times a week", times),
+            Recurrence::OnDays { days } if *days == WEEKDAYS => write!(f, "on weekdays"),
+            Recurrence::OnDays { days } if *days == WEEKEND => write!(f, "on weekends"),
+            Recurrence::OnDays { days } => {
+                let names: Vec<String> = days.iter().map(|d| d.to_string()).collect();
+                match names.split_last() {
+                    Some((last, rest)) if !rest.is_empty() => write!(f, "on {} and {}", rest.join(", "), last),
+                    _ => write!(f, "on {}", names.join("")),
+                }
+            }
+            Recurrence::EveryNDays { days: 2 } => write!(f, "every other day"),
+            Recurrence::EveryNDays { days } => write!(f, "every {} days", days),
+        }
+    }
+}
+
+impl fmt::Display for Cadence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.recurrence)?;
+        if let Some(target) = self.target {
+            write!(f, ", target {}", target)?;
+        }
+        if let Some(until) = self.until {
+            write!(f, ", until {}", until.format("%a %Y-%m-%d"))?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses the schedule in a plan, or returns `None` when it has none
+/// the client recognizes; the server then reads it from the text. Fails
+/// on an end date that can't be represented, like "for 400000000 years".
+pub fn parse_cadence(input: &str) -> Result<Option<Cadence>> {
+    parse_cadence_on(input, Local::now().date_naive())
+}
+
+/// The schedule of a plan that's already on the server, if it can be read.
+/// `cadence` is how the server reads it, used when the goal alone isn't enough.
+pub fn plan_cadence(goal: &str, cadence: Option<&str>) -> Option<Cadence> {
+    let read = |text: &str| parse_cadence(text).ok().flatten();
+    read(goal).or_else(|| cadence.and_then(read))
+}
+
+fn parse_cadence_on(input: &str, today: NaiveDate) -> Result<Option<Cadence>> {
+    let (recurrence, until, rest) = split_goal(input, today)?;
+    let target = parse_log(&rest).and_then(|parsed| parsed.count);
+    Ok(recurrence.map(|recurrence| Cadence { recurrence, until, target }))
+}
+
+/// What a plan is about without its schedule, e.g. "pullup" for "10 pullups every day".
+pub fn plan_activity(goal: &str) -> Option<String> {
+    let (_, _, rest) = split_goal(goal, Local::now().date_naive()).ok()?;
+    parse_log(&rest).and_then(|parsed| parsed.activity)
+}
+
+/// Splits a goal into its recurrence, end date and everything else.
+fn split_goal(input: &str, today: NaiveDate) -> Result<(Option<Recurrence>, Option<NaiveDate>, String)> {
+    let lowered = input.to_lowercase().replace(['/', ','], " ");
+    let tokens: Vec<&str> = lowered.split_whitespace().collect();
+
+    let mut recurrence = None;
+    let mut until = None;
+    let mut rest = Vec::new();
+    let mut i = 0;
+
+    while i < tokens.len() {
+        if until.is_none()
+            && let Some((day, consumed)) = end_date(&tokens[i..], today)?
+        {
+            until = Some(day);
+            i += consumed;
+            continue;
+        }
+        if recurrence.is_none()
+            && let Some((found, consumed)) = recurrence_at(&tokens[i..])
+        {
+            recurrence = Some(found);
+            i += consumed;
+            continue;
+        }
+        rest.push(tokens[i]);
+        i += 1;
+    }
+
+    Ok((recurrence, until, rest.join(" ")))
+}
+
+/// A recurrence starting at the first token, with how many tokens it spans.
+fn recurrence_at(tokens: &[&str]) -> Option<(Recurrence, usize)> {
+    let word = |i: usize| tokens.get(i).copied().unwrap_or_default();
+
+    match (word(0), word(1), word(2)) {
+        ("daily" | "everyday", ..) => return Some((Recurrence::Daily, 1)),
+        ("every" | "each" | "a" | "per", "day", _) => return Some((Recurrence::Daily, 2)),
+        ("every", "other", "day") => return Some((Recurrence::EveryNDays { days: 2 }, 3)),
+        ("weekly", ..) => return Some((Recurrence::PerWeek { times: 1 }, 1)),
+        ("every", "week", _) => return Some((Recurrence::PerWeek { times: 1 }, 2)),
+        ("every" | "on", "weekday" | "weekdays", _) => return Some((on_days(&WEEKDAYS), 2)),
+        ("every" | "on", "weekend" | "weekends", _) => return Some((on_days(&WEEKEND), 2)),
+        ("weekdays", ..) => return Some((on_days(&WEEKDAYS), 1)),
+        ("weekends", ..) => return Some((on_days(&WEEKEND), 1)),
+        ("every", n, "days" | "day" | "d") => {
+            return match count(n)? {
+                0 => None,
+                1 => Some((Recurrence::Daily, 3)),
+                days => Some((Recurrence::EveryNDays { days }, 3)),
+            };
+        }
+        _ => {}
+    }
+
+    if let Some(found) = per_week(tokens) {
+        return Some(found);
+    }
+
+    // "on mondays and thursdays", "every mon, wed, fri", "tuesdays"
+    let lead = usize::from(matches!(word(0), "every" | "on"));
+    let mut days = Vec::new();
+    let mut plural = false;
+    let mut end = lead;
+    let mut at = lead;
+    while at < tokens.len() {
+        match weekday(tokens[at]) {
+            Some((day, is_plural)) => {
+                days.push(day);
+                plural |= is_plural;
+                at += 1;
+                end = at;
+            }
+            None if !days.is_empty() && matches!(tokens[at], "and" | "&") => at += 1,
+            None => break,
+        }
+    }
+    // A lone "monday" is more likely part of the goal than a schedule
+    if days.is_empty() || (lead == 0 && !plural && days.len() < 2) {
+        return None;
+    }
+    Some((on_days(&days), end))
+}
+
+/// "3 times a week", "3x per week", "3x/week", "twice a week", "3 days a week"
+fn per_week(tokens: &[&str]) -> Option<(Recurrence, usize)> {
+    let first = *tokens.first()?;
+    let (times, mut at) = match first.strip_suffix('x').and_then(count) {
+        Some(times) => (times, 1),
+        None => match first {
+            "once" => (1, 1),
+            "twice" => (2, 1),
+            "thrice" => (3, 1),
+            _ => (count(first)?, 1),
+        },
+    };
+    if matches!(tokens.get(at), Some(&("times" | "time" | "x" | "days" | "sessions"))) {
+        at += 1;
+    }
+    if matches!(tokens.get(at), Some(&("a" | "per" | "every" | "each"))) {
+        at += 1;
+    }
+    if !matches!(tokens.get(at), Some(&("week" | "wk" | "w"))) || times == 0 || times > 7 {
+        return None;
+    }
+    let recurrence = if times == 7 { Recurrence::Daily } else { Recurrence::PerWeek { times } };
+    Some((recurrence, at + 1))
+}
+
+/// An end date starting at the first token: "until June", "till 2025-06-30",
+/// "through friday", "for 3 weeks". `None` when there isn't one.
+fn end_date(tokens: &[&str], today: NaiveDate) -> Result<Option<(NaiveDate, usize)>> {
+    match tokens.first().copied() {
+        Some("until" | "till" | "til" | "through" | "thru" | "by") => {
+            // Longest reading first, so "june 15" isn't taken as just "june"
+            Ok((1..=3.min(tokens.len() - 1))
+                .rev()
+                .find_map(|len| Some((day_named(&tokens[1..=len], today)?, len + 1))))
+        }
+        Some("for") => {
+            let (Some(amount), Some(unit)) = (tokens.get(1), tokens.get(2)) else {
+                return Ok(None);
+            };
+            let Some(amount) = count(amount).or_else(|| matches!(*amount, "a" | "an" | "one").then_some(1)) else {
+                return Ok(None);
+            };
+            let end = match unit.trim_end_matches('s') {
+                "day" => today.checked_add_days(Days::new(u64::from(amount))),
+                "week" => today.checked_add_days(Days::new(u64::from(amount) * 7)),
+                "month" => today.checked_add_months(Months::new(amount)),
+                "year" => amount.checked_mul(12).and_then(|months| today.checked_add_months(Months::new(months))),
+                _ => return Ok(None),
+            };
+            let end = end
+                .and_then(|end| end.pred_opt())
+                .with_context(|| format!("\"for {} {}\" ends too far in the future", amount, unit))?;
+            Ok(Some((end, 3)))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// "june" (its last day), "june 15", "15th june", "end of month", or anything
+/// `time::parse_schedule_day` reads.
+fn day_named(words: &[&str], today: NaiveDate) -> Option<NaiveDate> {
+    match words {
+        [word] => match month_number(word) {
+            Some(month) => {
+                let year = if month < today.month() { today.year() + 1 } else { today.year() };
+                last_day_of(year, month)
+            }
+            None => day_phrase(words, today),
+        },
+        [a, b] => {
+            let (month, day) = match (month_number(a), month_number(b)) {
+                (Some(month), None) => (month, day_of_month(b)?),
+                (None, Some(month)) => (month, day_of_month(a)?),
+                _ => return day_phrase(words, today),
+            };
+            let this_year = NaiveDate::from_ymd_opt(today.year(), month, day)?;
+            if this_year < today { NaiveDate::from_ymd_opt(today.year() + 1, month, day) } else { Some(this_year) }
+        }
+        _ => day_phrase(words, today),
+    }
+}
+
+fn day_phrase(words: &[&str], today: NaiveDate) -> Option<NaiveDate> {
+    match words {
+        ["end", "of", "month"] => last_day_of(today.year(), today.month()),
+        ["end", "of", "year"] => NaiveDate::from_ymd_opt(today.year(), 12, 31),
+        _ => time::parse_schedule_day(&words.join(" ")).ok(),
+    }
+}
+
+fn last_day_of(year: i32, month: u32) -> Option<NaiveDate> {
+    NaiveDate::from_ymd_opt(year, month, 1)?.checked_add_months(Months::new(1))?.pred_opt()
+}
+
+fn month_number(word: &str) -> Option<u32> {
+    word.parse::<Month>().ok().map(|m| m.number_from_month())
+}
+
+fn day_of_month(word: &str) -> Option<u32> {
+    let digits = word.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    digits.parse().ok().filter(|day| (1..=31).contains(day))
+}
+
+fn weekday(word: &str) -> Option<(Weekday, bool)> {
+    if let Ok(day) = word.parse::<Weekday>() {
+        return Some((day, false));
+    }
+    let singular = word.strip_suffix('s')?;
+    singular.parse::<Weekday>().ok().filter(|_| singular.len() > 3).map(|day| (day, true))
+}
+
+fn on_days(days: &[Weekday]) -> Recurrence {
+    let mut days = days.to_vec();
+    days.sort_by_key(|d| d.num_days_from_monday());
+    days.dedup();
+    if days.len() == 7 {
+        return Recurrence::Daily;
+    }
+    Recurrence::OnDays { days }
+}
+
+/// "3" or "three"
+fn count(word: &str) -> Option<u32> {
+    if let Ok(n) = word.parse() {
+        return Some(n);
+    }
+    let words = ["one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten"];
+    words.iter().position(|w| *w == word).map(|i| i as u32 + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Weekday::*;
+
+    /// A Sunday, so "this year" and "next year" readings are predictable.
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 10, 18).unwrap()
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(y, m, d)
+    }
+
+    fn parse(input: &str) -> Option<Cadence> {
+        parse_cadence_on(input, today()).unwrap()
+    }
+
+    #[test]
+    fn recurrences() {
+        let days = |days: &[Weekday]| Recurrence::OnDays { days: days.to_vec() };
+        let cases = [
+            ("pullups daily", Recurrence::Daily),
+            ("pullups everyday", Recurrence::Daily),
+            ("pullups every day", Recurrence::Daily),
+            ("pullups each day", Recurrence::Daily),
+            ("pullups per day", Recurrence::Daily),
+            ("stretch 7 times a week", Recurrence::Daily),
+            ("stretch every 1 day", Recurrence::Daily),
+            ("run every other day", Recurrence::EveryNDays { days: 2 }),
+            ("run every 3 days", Recurrence::EveryNDays { days: 3 }),
+            ("run every three days", Recurrence::EveryNDays { days: 3 }),
+            ("run every 4 d", Recurrence::EveryNDays { days: 4 }),
+            ("swim weekly", Recurrence::PerWeek { times: 1 }),
+            ("swim every week", Recurrence::PerWeek { times: 1 }),
+            ("swim once a week", Recurrence::PerWeek { times: 1 }),
+            ("swim twice a week", Recurrence::PerWeek { times: 2 }),
+            ("swim thrice a week", Recurrence::PerWeek { times: 3 }),
+            ("run 3 times a week", Recurrence::PerWeek { times: 3 }),
+            ("run 3x per week", Recurrence::PerWeek { times: 3 }),
+            ("run 3x/week", Recurrence::PerWeek { times: 3 }),
+            ("run 3 x a week", Recurrence::PerWeek { times: 3 }),
+            ("run 4 days a week", Recurrence::PerWeek { times: 4 }),
+            ("run 2 sessions per wk", Recurrence::PerWeek { times: 2 }),
+            ("plank on weekdays", days(&[Mon, Tue, Wed, Thu, Fri])),
+            ("plank every weekday", days(&[Mon, Tue, Wed, Thu, Fri])),
+            ("plank weekdays", days(&[Mon, Tue, Wed, Thu, Fri])),
+            ("hike on weekends", days(&[Sat, Sun])),
+            ("hike every weekend", days(&[Sat, Sun])),
+            ("hike weekends", days(&[Sat, Sun])),
+            ("lift on mondays and thursdays", days(&[Mon, Thu])),
+            ("lift every mon, wed, fri", days(&[Mon, Wed, Fri])),
+            ("lift on friday & monday", days(&[Mon, Fri])),
+            ("lift tuesdays", days(&[Tue])),
+            ("lift on monday", days(&[Mon])),
+            ("lift monday and friday", days(&[Mon, Fri])),
+            ("lift every day of the week on mon tue wed thu fri sat sun", Recurrence::Daily),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse(input).map(|c| c.recurrence), Some(expected), "{}", input);
+        }
+    }
+
+    #[test]
+    fn targets() {
+        let cases = [
+            ("10 pullups every day", Some(10)),
+            ("every day 10 pullups", Some(10)),
+            ("run 3x per week", None),
+            ("20 crunches on weekdays", Some(20)),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse(input).and_then(|c| c.target), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn end_dates() {
+        let cases = [
+            ("run daily until june", date(2027, 6, 30)),
+            ("run daily until december", date(2026, 12, 31)),
+            ("run daily until october", date(2026, 10, 31)),
+            ("run daily until june 15", date(2027, 6, 15)),
+            ("run daily till 15th june", date(2027, 6, 15)),
+            ("run daily until december 24", date(2026, 12, 24)),
+            ("run daily til 2026-12-31", date(2026, 12, 31)),
+            ("run daily by end of month", date(2026, 10, 31)),
+            ("run daily through end of year", date(2026, 12, 31)),
+            ("run daily for 10 days", date(2026, 10, 27)),
+            ("run daily for 3 weeks", date(2026, 11, 7)),
+            ("run daily for a week", date(2026, 10, 24)),
+            ("run daily for one month", date(2026, 11, 17)),
+            ("run daily for 2 years", date(2028, 10, 17)),
+            ("run daily", None),
+            ("run daily for fun", None),
+            ("run daily for 5 parsecs", None),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse(input).and_then(|c| c.until), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn not_a_schedule() {
+        for input in [
+            "",
+            "10 pullups",
+            "run monday",
+            "run every blah",
+            "run every 0 days",
+            "run 8 times a week",
+            "run 0x per week",
+            "read a book",
+            "every",
+        ] {
+            assert_eq!(parse(input), None, "{}", input);
+        }
+    }
+
+    #[test]
+    fn end_dates_out_of_range() {
+        for input in ["run for 400000000 years every day", "run for 300000 years daily", "run daily for 4000000000 days"] {
+            assert!(parse_cadence_on(input, today()).is_err(), "{}", input);
+            assert_eq!(plan_cadence(input, None), None, "{}", input);
+            assert_eq!(plan_activity(input), None, "{}", input);
+        }
+    }
+
+    #[test]
+    fn server_cadence_fallback() {
+        assert_eq!(plan_cadence("get fit", Some("3x per week")).map(|c| c.recurrence), Some(Recurrence::PerWeek { times: 3 }));
+        assert_eq!(plan_cadence("get fit", Some("whenever")), None);
+    }
+
+    #[test]
+    fn activities() {
+        let cases = [
+            ("10 pullups every day", Some("pullup")),
+            ("run 3x per week until june", Some("run")),
+            ("20 crunches on weekdays for 2 weeks", Some("crunch")),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(plan_activity(input).as_deref(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn display() {
+        let cases = [
+            ("10 pullups every day until 2027-06-30", "every day, target 10, until Wed 2027-06-30"),
+            ("swim once a week", "once a week"),
+            ("swim twice a week", "twice a week"),
+            ("run 3x per week", "3 times a week"),
+            ("plank on weekdays", "on weekdays"),
+            ("hike weekends", "on weekends"),
+            ("lift on mondays and thursdays", "on Mon and Thu"),
+            ("lift every mon wed fri", "on Mon, Wed and Fri"),
+            ("lift tuesdays", "on Tue"),
+            ("run every other day", "every other day"),
+            ("run every 3 days", "every 3 days"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse(input).unwrap().to_string(), expected, "{}", input);
+        }
+    }
+}
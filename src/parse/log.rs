@@ -0,0 +1,410 @@
+//! Reads the structure out of a log like "10 100m sprints" or "3x10 squats 60kg"
+//! so the user can see how it will be understood before it's sent.
+
+use serde::Serialize;
+use std::fmt;
+
+/// Words that join the parts of a log without meaning anything themselves.
+const FILLER: &[&str] = &["of", "in", "for", "a", "an", "the", "and", "x", "at", "with", "rep", "reps", "did"];
+
+/// Activities where a bare "k" means kilometres, as in "5k run". Anywhere
+/// else it means thousands, as in "10k steps".
+const DISTANCE_ACTIVITIES: &[&str] = &[
+    "run", "jog", "walk", "hike", "race", "swim", "row", "ride", "bike", "cycle", "ski", "trail",
+];
+
+#[derive(Clone, Copy)]
+enum Dimension {
+    Distance,
+    Duration,
+    Weight,
+}
+
+/// A number with a unit, normalized to the short form ("km", "kg").
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct Measure {
+    pub value: f64,
+    pub unit: &'static str,
+}
+
+impl fmt::Display for Measure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.value, self.unit)
+    }
+}
+
+#[derive(Serialize, Clone, Default, PartialEq, Debug)]
+pub struct ParsedLog {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sets: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance: Option<Measure>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_secs: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weight: Option<Measure>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub activity: Option<String>,
+}
+
+impl ParsedLog {
+    fn is_empty(&self) -> bool {
+        *self == ParsedLog::default()
+    }
+}
+
+impl fmt::Display for ParsedLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(sets) = self.sets {
+            parts.push(format!("sets: {}", sets));
+        }
+        if let Some(count) = self.count {
+            parts.push(format!("count: {}", count));
+        }
+        if let Some(distance) = &self.distance {
+            parts.push(format!("distance: {}", distance));
+        }
+        if let Some(secs) = self.duration_secs {
+            parts.push(format!("duration: {}", format_duration(secs)));
+        }
+        if let Some(weight) = &self.weight {
+            parts.push(format!("weight: {}", weight));
+        }
+        if let Some(activity) = &self.activity {
+            parts.push(format!("activity: {}", activity));
+        }
+        write!(f, "{{{}}}", parts.join(", "))
+    }
+}
+
+/// Parses a log, or returns `None` when nothing in it was recognized.
+/// Hashtags (from `--tag`) are left to the server.
+pub fn parse_log(input: &str) -> Option<ParsedLog> {
+    let lowered = input.to_lowercase();
+    let tokens: Vec<&str> = lowered
+        .split_whitespace()
+        .filter(|token| !token.starts_with('#'))
+        .collect();
+
+    let mut parsed = ParsedLog::default();
+    let mut activity = Vec::new();
+    // "5k" can only be read once the activity is known
+    let mut thousands = None;
+    let mut i = 0;
+
+    while i < tokens.len() {
+        let token = tokens[i];
+        let next = tokens.get(i + 1).copied();
+        i += 1;
+
+        // "3x10"
+        if let Some((sets, count)) = sets_by_reps(token) {
+            parsed.sets = Some(sets);
+            parsed.count = Some(count);
+            continue;
+        }
+        // "1:30", "1:02:03"
+        if let Some(secs) = clock_duration(token) {
+            parsed.duration_secs = Some(secs);
+            continue;
+        }
+        // "1h30", "1h30min"
+        if let Some(secs) = hours_and_minutes(token) {
+            parsed.duration_secs = Some(secs);
+            continue;
+        }
+
+        let (number, suffix) = split_number(token);
+        let Some(value) = number.and_then(|n| n.parse::<f64>().ok()) else {
+            // A unit with no number, like a stray "h", isn't an activity
+            if !FILLER.contains(&token) && measure(1.0, token).is_none() && token != "k" {
+                activity.push(token);
+            }
+            continue;
+        };
+
+        // The unit is glued on ("100m") or the next word ("100 m")
+        let (unit, consumed) = match suffix {
+            "" => (next.unwrap_or_default(), true),
+            suffix => (suffix, false),
+        };
+
+        match unit {
+            // "3 x 10" and "3x 10"
+            "x" if next_number(&tokens, i + usize::from(consumed)).is_some() => {
+                let at = i + usize::from(consumed);
+                parsed.sets = Some(value as u32);
+                parsed.count = next_number(&tokens, at);
+                i = at + 1;
+            }
+            "set" | "sets" => {
+                parsed.sets = Some(value as u32);
+                i += usize::from(consumed);
+            }
+            "k" => {
+                thousands = Some(value);
+                i += usize::from(consumed);
+            }
+            unit => match measure(value, unit) {
+                Some((Dimension::Distance, measure)) => {
+                    parsed.distance = Some(measure);
+                    i += usize::from(consumed);
+                }
+                Some((Dimension::Weight, measure)) => {
+                    parsed.weight = Some(measure);
+                    i += usize::from(consumed);
+                }
+                Some((Dimension::Duration, measure)) => {
+                    parsed.duration_secs = Some(measure.value as u32);
+                    i += usize::from(consumed);
+                }
+                // A second count starts another exercise, e.g. "5 pushups 10 squats",
+                // which is left to the server; only the first is read here
+                None if parsed.count.is_some() && !activity.is_empty() => break,
+                // "10pushups" or a bare count
+                None if parsed.count.is_none() && value.fract() == 0.0 => {
+                    parsed.count = Some(value as u32);
+                    if !consumed && !FILLER.contains(&suffix) {
+                        activity.push(suffix);
+                    }
+                }
+                None => activity.push(token),
+            },
+        }
+    }
+
+    if let Some(last) = activity.last_mut() {
+        *last = singular(last);
+    }
+    if let Some(value) = thousands {
+        if activity.iter().any(|word| DISTANCE_ACTIVITIES.contains(&singular(word))) {
+            parsed.distance = Some(Measure { value, unit: "km" });
+        } else if parsed.count.is_none() {
+            parsed.count = Some((value * 1000.0).round() as u32);
+        }
+    }
+    if !activity.is_empty() {
+        parsed.activity = Some(activity.join(" "));
+    }
+
+    (!parsed.is_empty()).then_some(parsed)
+}
+
+fn next_number(tokens: &[&str], at: usize) -> Option<u32> {
+    tokens.get(at)?.parse().ok()
+}
+
+/// Splits "100m" into ("100", "m"); words come back as (None, word).
+fn split_number(token: &str) -> (Option<&str>, &str) {
+    let end = token
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(token.len());
+    match end {
+        0 => (None, token),
+        end => (Some(&token[..end]), &token[end..]),
+    }
+}
+
+fn sets_by_reps(token: &str) -> Option<(u32, u32)> {
+    let (sets, reps) = token.split_once(['x', '×'])?;
+    Some((sets.parse().ok()?, reps.parse().ok()?))
+}
+
+fn clock_duration(token: &str) -> Option<u32> {
+    let parts: Vec<u32> = token.split(':').map(|p| p.parse().ok()).collect::<Option<_>>()?;
+    match parts.as_slice() {
+        [minutes, seconds] if *seconds < 60 => minutes.checked_mul(60)?.checked_add(*seconds),
+        [hours, minutes, seconds] if *minutes < 60 && *seconds < 60 => {
+            hours.checked_mul(3600)?.checked_add(minutes * 60 + seconds)
+        }
+        _ => None,
+    }
+}
+
+fn hours_and_minutes(token: &str) -> Option<u32> {
+    let (hours, rest) = token.split_once('h')?;
+    let minutes = rest.trim_end_matches("min").trim_end_matches('m');
+    if minutes.is_empty() {
+        return None;
+    }
+    let (hours, minutes) = (hours.parse::<u32>().ok()?, minutes.parse::<u32>().ok()?);
+    hours.checked_mul(3600)?.checked_add(minutes.checked_mul(60)?)
+}
+
+/// Normalizes a unit; durations come back in seconds.
+fn measure(value: f64, unit: &str) -> Option<(Dimension, Measure)> {
+    let (dimension, unit, scale) = match unit {
+        "m" | "meter" | "meters" | "metre" | "metres" => (Dimension::Distance, "m", 1.0),
+        "km" | "kms" | "kilometer" | "kilometers" | "kilometre" | "kilometres" => {
+            (Dimension::Distance, "km", 1.0)
+        }
+        "mi" | "mile" | "miles" => (Dimension::Distance, "mi", 1.0),
+        "yd" | "yds" | "yard" | "yards" => (Dimension::Distance, "yd", 1.0),
+        "s" | "sec" | "secs" | "second" | "seconds" => (Dimension::Duration, "s", 1.0),
+        "min" | "mins" | "minute" | "minutes" => (Dimension::Duration, "s", 60.0),
+        "h" | "hr" | "hrs" | "hour" | "hours" => (Dimension::Duration, "s", 3600.0),
+        "kg" | "kgs" | "kilo" | "kilos" => (Dimension::Weight, "kg", 1.0),
+        "lb" | "lbs" | "pound" | "pounds" => (Dimension::Weight, "lb", 1.0),
+        _ => return None,
+    };
+    Some((dimension, Measure { value: value * scale, unit }))
+}
+
+/// "sprints" -> "sprint", "crunches" -> "crunch", "press", "bus" and "tennis" stay.
+pub fn singular(word: &str) -> &str {
+    if word.ends_with("ss") || word.ends_with("us") || word.ends_with("is") {
+        return word;
+    }
+    for suffix in ["ches", "shes", "xes", "sses"] {
+        if word.ends_with(suffix) {
+            return &word[..word.len() - 2];
+        }
+    }
+    word.strip_suffix('s').filter(|stem| stem.len() > 1).unwrap_or(word)
+}
+
+/// 5400 -> "1h30min"
+pub fn format_duration(secs: u32) -> String {
+    let (hours, minutes, seconds) = (secs / 3600, secs % 3600 / 60, secs % 60);
+    let mut out = String::new();
+    if hours > 0 {
+        out.push_str(&format!("{}h", hours));
+    }
+    if minutes > 0 {
+        out.push_str(&format!("{}min", minutes));
+    }
+    if seconds > 0 || out.is_empty() {
+        out.push_str(&format!("{}s", seconds));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn km(value: f64) -> Option<Measure> {
+        Some(Measure { value, unit: "km" })
+    }
+
+    fn log(count: Option<u32>, activity: &str) -> ParsedLog {
+        ParsedLog { count, activity: Some(activity.to_string()), ..Default::default() }
+    }
+
+    #[test]
+    fn counts() {
+        let cases = [
+            ("5 pushups", log(Some(5), "pushup")),
+            ("10pushups", log(Some(10), "pushup")),
+            ("pushups 5", log(Some(5), "pushup")),
+            ("did 20 reps of squats", log(Some(20), "squat")),
+            ("5 pushups 10 squats", log(Some(5), "pushup")),
+            ("12 kettlebell swings", log(Some(12), "kettlebell swing")),
+            ("3x10 squats", ParsedLog { sets: Some(3), ..log(Some(10), "squat") }),
+            ("3×10 squats", ParsedLog { sets: Some(3), ..log(Some(10), "squat") }),
+            ("3 x 10 squats", ParsedLog { sets: Some(3), ..log(Some(10), "squat") }),
+            ("3x 10 squats", ParsedLog { sets: Some(3), ..log(Some(10), "squat") }),
+            ("3 sets of 12 lunges", ParsedLog { sets: Some(3), ..log(Some(12), "lunge") }),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_log(input), Some(expected), "{}", input);
+        }
+    }
+
+    #[test]
+    fn units() {
+        let cases = [
+            ("run 5km", ParsedLog { distance: km(5.0), activity: Some("run".into()), ..Default::default() }),
+            ("run 5 km", ParsedLog { distance: km(5.0), activity: Some("run".into()), ..Default::default() }),
+            ("5k run", ParsedLog { distance: km(5.0), activity: Some("run".into()), ..Default::default() }),
+            ("run 10 k", ParsedLog { distance: km(10.0), activity: Some("run".into()), ..Default::default() }),
+            ("10k steps", log(Some(10000), "step")),
+            ("2.5k jump ropes", log(Some(2500), "jump rope")),
+            ("2.5 kilometres walk", ParsedLog { distance: km(2.5), activity: Some("walk".into()), ..Default::default() }),
+            (
+                "10 100m sprints",
+                ParsedLog { distance: Some(Measure { value: 100.0, unit: "m" }), ..log(Some(10), "sprint") },
+            ),
+            ("3 miles", ParsedLog { distance: Some(Measure { value: 3.0, unit: "mi" }), ..Default::default() }),
+            (
+                "3x10 squats 60kg",
+                ParsedLog { sets: Some(3), weight: Some(Measure { value: 60.0, unit: "kg" }), ..log(Some(10), "squat") },
+            ),
+            (
+                "deadlift 225 lbs",
+                ParsedLog { weight: Some(Measure { value: 225.0, unit: "lb" }), activity: Some("deadlift".into()), ..Default::default() },
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_log(input), Some(expected), "{}", input);
+        }
+    }
+
+    #[test]
+    fn durations() {
+        let cases = [
+            ("plank 90s", 90),
+            ("plank 90 sec", 90),
+            ("read 30 min", 1800),
+            ("read for 30 minutes", 1800),
+            ("yoga 1h", 3600),
+            ("yoga 2 hours", 7200),
+            ("yoga 1h30", 5400),
+            ("yoga 1h30min", 5400),
+            ("run 25:30", 1530),
+            ("run 1:02:03", 3723),
+        ];
+        for (input, expected) in cases {
+            let parsed = parse_log(input).unwrap();
+            assert_eq!(parsed.duration_secs, Some(expected), "{}", input);
+            assert!(parsed.activity.is_some(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn plurals() {
+        let cases = [
+            ("5 pushups", "pushup"),
+            ("5 crunches", "crunch"),
+            ("5 wishes", "wish"),
+            ("5 boxes", "box"),
+            ("5 presses", "press"),
+            ("bench press", "bench press"),
+            ("5 bus", "bus"),
+            ("tennis", "tennis"),
+            ("yoga", "yoga"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_log(input).and_then(|p| p.activity).as_deref(), Some(expected), "{}", input);
+        }
+    }
+
+    #[test]
+    fn hashtags_are_left_to_the_server() {
+        assert_eq!(parse_log("5 pushups #morning #gym"), Some(log(Some(5), "pushup")));
+        assert_eq!(parse_log("#plan"), None);
+    }
+
+    #[test]
+    fn malformed() {
+        for input in ["", "   ", "h", "km", "the a of", "#tag", "x"] {
+            assert_eq!(parse_log(input), None, "{:?}", input);
+        }
+        // Too large to be durations, so they aren't read as such
+        assert_eq!(parse_log("99999999:00").and_then(|p| p.duration_secs), None);
+        assert_eq!(parse_log("9999999h30").and_then(|p| p.duration_secs), None);
+        assert_eq!(parse_log("1:75").and_then(|p| p.duration_secs), None);
+        assert_eq!(parse_log("1.5 pushups").map(|p| p.count), Some(None));
+    }
+
+    #[test]
+    fn format_durations() {
+        assert_eq!(format_duration(0), "0s");
+        assert_eq!(format_duration(45), "45s");
+        assert_eq!(format_duration(5400), "1h30min");
+        assert_eq!(format_duration(3723), "1h2min3s");
+    }
+}
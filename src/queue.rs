@@ -0,0 +1,68 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use uuid::Uuid;
+use crate::config;
+
+/// A log that couldn't be delivered and is waiting to be replayed.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct QueuedLog {
+    /// Sent as the `Idempotency-Key`, so a log that reached the server before a
+    /// crash or timeout isn't stored twice when it's sent again.
+    #[serde(default = "Uuid::new_v4")]
+    pub key: Uuid,
+    pub raw_input: String,
+    pub captured_at: DateTime<Utc>,
+    /// Set when the log was backdated with `--at`; otherwise it happened at `captured_at`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub occurred_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+fn queue_path() -> PathBuf {
+    queue_path_for(&config::get().profile)
+}
+
+fn queue_path_for(profile: &str) -> PathBuf {
+    config::profile_dir_for(profile).join("queue.json")
+}
+
+pub fn load_queue() -> Result<Vec<QueuedLog>> {
+    load_queue_for(&config::get().profile)
+}
+
+pub fn load_queue_for(profile: &str) -> Result<Vec<QueuedLog>> {
+    let path = queue_path_for(profile);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let json = fs::read_to_string(&path).context("Failed to read log queue")?;
+    serde_json::from_str(&json).context("Failed to parse log queue")
+}
+
+pub fn save_queue(queue: &[QueuedLog]) -> Result<()> {
+    let path = queue_path();
+    if queue.is_empty() {
+        if path.exists() {
+            fs::remove_file(&path).context("Failed to clear log queue")?;
+        }
+        return Ok(());
+    }
+
+    fs::create_dir_all(path.parent().unwrap())?;
+    // Write to a temp file and rename so a crash never leaves a half-written queue
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_string_pretty(queue)?).context("Failed to write log queue")?;
+    fs::rename(&tmp, &path).context("Failed to write log queue")?;
+
+    Ok(())
+}
+
+pub fn enqueue(entry: QueuedLog) -> Result<()> {
+    let mut queue = load_queue()?;
+    queue.push(entry);
+    save_queue(&queue)
+}
@@ -0,0 +1,48 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use crate::config;
+
+/// Small bits of per-profile bookkeeping that don't belong in the config file.
+#[derive(Serialize, Deserialize, Default)]
+pub struct State {
+    /// Last time every queued log had been delivered to the server.
+    pub last_sync: Option<DateTime<Utc>>,
+    /// `imp remind` stays silent until then.
+    pub snoozed_until: Option<DateTime<Utc>>,
+    /// When `imp remind` last notified, so a restart doesn't repeat it.
+    pub last_reminder: Option<DateTime<Utc>>,
+    /// The notification to replace instead of stacking a new one.
+    pub notification_id: Option<u32>,
+    /// When `imp prompt` last started a background refresh.
+    pub refresh_started_at: Option<DateTime<Utc>>,
+}
+
+fn state_path() -> PathBuf {
+    config::profile_dir().join("state.json")
+}
+
+pub fn load_state() -> State {
+    fs::read_to_string(state_path())
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_state(state: &State) -> Result<()> {
+    let path = state_path();
+    fs::create_dir_all(path.parent().unwrap())?;
+    // Written aside and renamed over, so `imp prompt` never reads half a file
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_string_pretty(state)?).context("Failed to write state")?;
+    fs::rename(&tmp, &path).context("Failed to write state")?;
+    Ok(())
+}
+
+pub fn mark_synced() -> Result<()> {
+    let mut state = load_state();
+    state.last_sync = Some(Utc::now());
+    save_state(&state)
+}
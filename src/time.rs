@@ -0,0 +1,246 @@
+//! Reading the times people type for `--at`: "07:30", "yesterday 18:00",
+//! "monday 7pm", "2h ago" or ISO 8601. Anything without an explicit offset
+//! is in the local time zone.
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Datelike, Days, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, Utc, Weekday};
+
+const EXAMPLES: &str = "e.g. 07:30, 7pm, yesterday 18:00, monday 7am, 2h ago or 2024-05-01 07:30";
+
+/// Parses `input` relative to now and rejects times in the future.
+pub fn parse_at(input: &str) -> Result<DateTime<Utc>> {
+    parse_at_from(input, Local::now())
+}
+
+fn parse_at_from(input: &str, now: DateTime<Local>) -> Result<DateTime<Utc>> {
+    let input = input.trim();
+
+    let time = absolute(input)
+        .or_else(|| relative(&input.to_lowercase(), now))
+        .with_context(|| format!("Can't read time \"{}\", use {}", input, EXAMPLES))?;
+
+    if time > now + slack() {
+        bail!("\"{}\" is in the future", input);
+    }
+    Ok(time.with_timezone(&Utc))
+}
+
+/// A minute of slack for clocks and typing.
+fn slack() -> Duration {
+    Duration::minutes(1)
+}
+
+/// The local calendar day of anything `parse_at` accepts, e.g. "monday" or "2 weeks ago".
+pub fn parse_day(input: &str) -> Result<NaiveDate> {
+    Ok(parse_at(input)?.with_timezone(&Local).date_naive())
+}
+
+/// A local calendar day for scheduling rather than logging, so weekdays look
+/// ahead: "2024-06-01", "today", "tomorrow", "friday" (the next one), "in 2 weeks".
+pub fn parse_schedule_day(input: &str) -> Result<NaiveDate> {
+    let today = Local::now().date_naive();
+    let input = input.trim().to_lowercase();
+
+    NaiveDate::parse_from_str(&input, "%Y-%m-%d")
+        .ok()
+        .or_else(|| upcoming(&input, today))
+        .with_context(|| format!("Can't read day \"{}\", use e.g. 2024-06-01, tomorrow, friday or in 2 weeks", input))
+}
+
+fn upcoming(input: &str, today: NaiveDate) -> Option<NaiveDate> {
+    match input.split_whitespace().collect::<Vec<_>>().as_slice() {
+        ["today"] => Some(today),
+        ["tomorrow"] => today.succ_opt(),
+        ["yesterday"] => today.pred_opt(),
+        ["in", amount @ ..] => today.checked_add_days(Days::new(u64::try_from(ago(&amount.concat())?.num_days()).ok()?)),
+        ["next", day] | [day] => {
+            let day: Weekday = day.parse().ok()?;
+            let ahead = (day.num_days_from_monday() + 7 - today.weekday().num_days_from_monday()) % 7;
+            today.checked_add_days(Days::new(u64::from(if ahead == 0 { 7 } else { ahead })))
+        }
+        _ => None,
+    }
+}
+
+fn absolute(input: &str) -> Option<DateTime<Local>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(input) {
+        return Some(time.with_timezone(&Local));
+    }
+
+    let naive = ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(input, format).ok())
+        // A bare date is taken as midday so it stays on that day in any zone
+        .or_else(|| NaiveDate::parse_from_str(input, "%Y-%m-%d").ok().map(|d| d.and_time(noon())))?;
+
+    local(naive)
+}
+
+fn relative(input: &str, now: DateTime<Local>) -> Option<DateTime<Local>> {
+    if input == "now" {
+        return Some(now);
+    }
+    if let Some(amount) = input.strip_suffix("ago") {
+        return now.checked_sub_signed(ago(amount)?);
+    }
+
+    let mut words: Vec<&str> = input.split_whitespace().collect();
+    let days_back = match words.as_slice() {
+        ["today", ..] => Some(0),
+        ["yesterday", ..] => Some(1),
+        ["last", day, ..] => day.parse::<Weekday>().ok().map(|day| match days_since(now, day) {
+            0 => 7,
+            n => n,
+        }),
+        [day, ..] => day.parse::<Weekday>().ok().map(|day| days_since(now, day)),
+        [] => None,
+    };
+    if days_back.is_some() {
+        let day_words = if words[0] == "last" { 2 } else { 1 };
+        words.drain(..day_words);
+    }
+    words.retain(|word| *word != "at");
+
+    let date = now.date_naive().checked_sub_days(Days::new(days_back.unwrap_or(0)))?;
+    let time = match (words.is_empty(), days_back) {
+        // "yesterday" on its own means this time yesterday
+        (true, Some(_)) => now.time(),
+        (true, None) => return None,
+        (false, _) => time_of_day(&words.concat())?,
+    };
+
+    let at = local(date.and_time(time))?;
+    // A bare "23:30" typed in the morning is last night
+    if days_back.is_none() && at > now + slack() {
+        return local(date.pred_opt()?.and_time(time));
+    }
+    Some(at)
+}
+
+/// A length of time like "30m", "2h" or "1 day".
+pub fn parse_duration(input: &str) -> Result<Duration> {
+    ago(&input.trim().to_lowercase())
+        .filter(|d| *d > Duration::zero())
+        .with_context(|| format!("Can't read duration \"{}\", use e.g. 30m, 2h or 1d", input.trim()))
+}
+
+/// "2h", "90 min", "3 days", "2 weeks"
+fn ago(amount: &str) -> Option<Duration> {
+    let amount = amount.replace(' ', "");
+    let digits = amount.find(|c: char| !c.is_ascii_digit())?;
+    let (count, unit) = amount.split_at(digits);
+    let count: i64 = count.parse().ok()?;
+
+    match unit {
+        "m" | "min" | "mins" | "minute" | "minutes" => Duration::try_minutes(count),
+        "h" | "hr" | "hrs" | "hour" | "hours" => Duration::try_hours(count),
+        "d" | "day" | "days" => Duration::try_days(count),
+        "w" | "wk" | "wks" | "week" | "weeks" => Duration::try_weeks(count),
+        _ => None,
+    }
+}
+
+/// "18:00", "7:30", "7pm", "7:30am", "noon", "midnight"
+fn time_of_day(input: &str) -> Option<NaiveTime> {
+    match input {
+        "noon" => return Some(noon()),
+        "midnight" => return NaiveTime::from_hms_opt(0, 0, 0),
+        _ => {}
+    }
+
+    let (clock, offset) = if let Some(clock) = input.strip_suffix("am") {
+        (clock, Some(0))
+    } else if let Some(clock) = input.strip_suffix("pm") {
+        (clock, Some(12))
+    } else {
+        (input, None)
+    };
+
+    let (hour, minute) = match clock.split_once(':') {
+        Some((hour, minute)) => (hour.parse::<u32>().ok()?, minute.parse::<u32>().ok()?),
+        // A bare number is only a time with am/pm, otherwise it's too ambiguous
+        None if offset.is_some() => (clock.parse::<u32>().ok()?, 0),
+        None => return None,
+    };
+
+    let hour = match offset {
+        Some(offset) if (1..=12).contains(&hour) => hour % 12 + offset,
+        Some(_) => return None,
+        None => hour,
+    };
+    NaiveTime::from_hms_opt(hour, minute, 0)
+}
+
+fn days_since(now: DateTime<Local>, day: Weekday) -> u64 {
+    let today = now.weekday().num_days_from_monday();
+    u64::from((today + 7 - day.num_days_from_monday()) % 7)
+}
+
+fn noon() -> NaiveTime {
+    NaiveTime::from_hms_opt(12, 0, 0).unwrap()
+}
+
+/// Times skipped by a DST change don't exist; repeated ones take the first.
+fn local(naive: NaiveDateTime) -> Option<DateTime<Local>> {
+    naive.and_local_timezone(Local).earliest()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sunday 2026-10-18, 11:25 local time.
+    fn now() -> DateTime<Local> {
+        at("2026-10-18 11:25")
+    }
+
+    fn at(text: &str) -> DateTime<Local> {
+        local(NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").unwrap()).unwrap()
+    }
+
+    fn parse(input: &str) -> DateTime<Local> {
+        parse_at_from(input, now()).unwrap().with_timezone(&Local)
+    }
+
+    #[test]
+    fn clock_times_earlier_today() {
+        assert_eq!(parse("07:30"), at("2026-10-18 07:30"));
+        assert_eq!(parse("7am"), at("2026-10-18 07:00"));
+        assert_eq!(parse("11:25"), at("2026-10-18 11:25"));
+        // Within the minute of slack
+        assert_eq!(parse("11:26"), at("2026-10-18 11:26"));
+    }
+
+    #[test]
+    fn later_clock_times_are_yesterday() {
+        assert_eq!(parse("23:59"), at("2026-10-17 23:59"));
+        assert_eq!(parse("7pm"), at("2026-10-17 19:00"));
+        assert_eq!(parse("at 11:30"), at("2026-10-17 11:30"));
+        assert_eq!(parse("noon"), at("2026-10-17 12:00"));
+    }
+
+    #[test]
+    fn out_of_range_amounts_are_errors() {
+        for input in ["99999999 weeks ago", "999999999999999999w ago", "9999999999999d ago"] {
+            let error = parse_at_from(input, now()).unwrap_err();
+            assert!(error.to_string().starts_with("Can't read time"), "{}", input);
+        }
+        assert!(parse_duration("999999999999999999w").is_err());
+    }
+
+    #[test]
+    fn explicit_days_are_kept() {
+        assert_eq!(parse("yesterday 18:00"), at("2026-10-17 18:00"));
+        assert_eq!(parse("friday 7pm"), at("2026-10-16 19:00"));
+        assert_eq!(parse("last sunday 9am"), at("2026-10-11 09:00"));
+        assert!(parse_at_from("today 23:59", now()).is_err());
+    }
+
+    #[test]
+    fn relative_and_absolute() {
+        assert_eq!(parse("2h ago"), at("2026-10-18 09:25"));
+        assert_eq!(parse("2026-10-01 07:30"), at("2026-10-01 07:30"));
+        assert!(parse_at_from("2026-10-19 07:30", now()).is_err());
+        assert!(parse_at_from("soonish", now()).is_err());
+    }
+}